@@ -0,0 +1,52 @@
+/*
+* bootstrap.js
+* unpacks a self extracting page
+* the payload holds the styles, scripts and body of the real document,
+* deflated and base64 encoded, the blobs stay outside of it
+*/
+
+(async () => {
+    const bootstrap = document.currentScript;
+    const payload = document.getElementById('htmlpacker-payload');
+    const compression = payload.getAttribute('compression') || 'none';
+
+    const bytes = Uint8Array.from(atob(payload.textContent.trim()), c => c.charCodeAt(0));
+    let stream = new Blob([bytes]).stream();
+    if (compression !== 'none') {
+        stream = stream.pipeThrough(new DecompressionStream(compression));
+    }
+    const text = await new Response(stream).text();
+    payload.remove();
+
+    const parsed = new DOMParser().parseFromString(text, 'text/html');
+    // only the payload's own scripts, the page's ran already
+    const scripts = [...parsed.querySelectorAll('script')];
+    document.head.append(...parsed.head.childNodes);
+    bootstrap.before(...parsed.body.childNodes);
+
+    // parsed scripts never run, fresh copies do, one after another
+    for (const old of scripts) {
+        const script = document.createElement('script');
+        for (const { name, value } of old.attributes) {
+            script.setAttribute(name, value);
+        }
+        script.text = old.text;
+        // created scripts are async, the ones in the page were not unless marked
+        const loaded = script.src && !old.hasAttribute('async') ?
+            new Promise(resolve => { script.onload = script.onerror = resolve; }) :
+            null;
+        old.replaceWith(script);
+        if (loaded) {
+            await loaded;
+        }
+    }
+
+    // the real ones went by while the payload was decoded,
+    // unless the document is still loading and they are yet to come
+    if (document.readyState !== 'loading') {
+        document.dispatchEvent(new Event('DOMContentLoaded', { bubbles: true }));
+    }
+    if (document.readyState === 'complete') {
+        window.dispatchEvent(new Event('load'));
+    }
+})();
@@ -63,6 +63,124 @@ window.addEventListener('DOMContentLoaded', runApp);
         return bytes;
     }
     
+    // base122, the same as the wasm decoder does it
+    // https://github.com/kevinAlbs/Base122
+    const BASE122_ILLEGALS = [0, 10, 13, 34, 38, 92];
+
+    function decodeBase122(text) {
+        // at most 14 bits per char
+        const bytes = new Uint8Array(Math.ceil(text.length * 14 / 8));
+        let length = 0;
+        let curByte = 0;
+        let bitOfByte = 0;
+
+        function push7(bits) {
+            bits = (bits << 1) & 0xff;
+            curByte |= bits >>> bitOfByte;
+            bitOfByte += 7;
+            if (bitOfByte >= 8) {
+                bytes[length++] = curByte;
+                bitOfByte -= 8;
+                curByte = (bits << (7 - bitOfByte)) & 0xff;
+            }
+        }
+
+        for (let i = 0; i < text.length; i++) {
+            const c = text.charCodeAt(i);
+            if (c > 127) {
+                const illegalIndex = (c >>> 8) & 7;
+                if (illegalIndex !== 7) {
+                    if (illegalIndex >= BASE122_ILLEGALS.length) {
+                        throw new Error("Invalid base122 text");
+                    }
+                    push7(BASE122_ILLEGALS[illegalIndex]);
+                }
+                push7(c & 127);
+            } else if (c !== 10) {
+                push7(c);
+            }
+        }
+        return bytes.subarray(0, length);
+    }
+
+    // 'wasm', 'native' or 'auto'
+    // a page without the decoder blob can only use the browser
+    function decoderMode() {
+        if (!document.getElementById('bin-wasm-decoder')) {
+            return 'native';
+        }
+        return window.htmlpacker?.decoder || 'wasm';
+    }
+
+    // DecompressionStream throws on a format it does not know
+    const nativeFormats = new Map();
+
+    function hasNative(compression) {
+        if (!nativeFormats.has(compression)) {
+            let supported = false;
+            try {
+                new DecompressionStream(compression);
+                supported = true;
+            } catch (e) {
+                supported = false;
+            }
+            nativeFormats.set(compression, supported);
+        }
+        return nativeFormats.get(compression);
+    }
+
+    async function decompressNative(bytes, compression) {
+        const stream = new Blob([bytes]).stream()
+            .pipeThrough(new DecompressionStream(compression));
+        return new Uint8Array(await new Response(stream).arrayBuffer());
+    }
+
+    // turn the text of a <pre> blob back into its original bytes
+    // the encoding and compression attributes say how it was packed
+    async function decodeBlob(element) {
+        const encoding = element.getAttribute('encoding') || 'base64';
+        const compression = element.getAttribute('compression') || 'none';
+        const mode = decoderMode();
+        // textContent, innerHTML would give back escaped entities
+        const text = element.textContent;
+
+        let bytes;
+        if (encoding === 'base122') {
+            bytes = mode === 'wasm'
+                ? wasm_decoder.decodeBase122(text)
+                : decodeBase122(text);
+        } else if (encoding === 'base64') {
+            bytes = b64ToBytes(text);
+        } else {
+            throw new Error(`Unknown encoding '${encoding}' for ${element.id}`);
+        }
+
+        if (compression === 'none') {
+            return bytes;
+        }
+        if (mode !== 'wasm' && hasNative(compression)) {
+            return decompressNative(bytes, compression);
+        }
+        if (mode === 'native') {
+            throw new Error(
+                `This browser cannot decompress '${compression}' for ${element.id} ` +
+                `and the page has no wasm decoder`
+            );
+        }
+
+        // the codec the packer picked is recorded on the blob
+        const decompressors = {
+            brotli: wasm_decoder.decompress,
+            gzip: wasm_decoder.decompressGzip,
+            deflate: wasm_decoder.decompressDeflate,
+            zstd: wasm_decoder.decompressZstd,
+        };
+        if (!(compression in decompressors)) {
+            throw new Error(`Unknown compression '${compression}' for ${element.id}`);
+        }
+        return decompressors[compression](bytes);
+    }
+
     async function loadDecoder(db, statusCallback) {
         console.log("Loading wasm decoder...");
         statusCallback?.("Loading decoder module...");
@@ -102,24 +220,44 @@ window.addEventListener('DOMContentLoaded', runApp);
         }
 
         statusCallback?.("Initializing decoder module...");
-        await wasm_decoder(wasmBytes);
+        await wasm_decoder({ module_or_path: wasmBytes });
         statusCallback?.("Decoder ready");
     }
 
-    async function loadApp(db, statusCallback) {
-        console.log("Loading wasm main app...");
+    // the glue packed for this app, or the global a script defined
+    function getGlue(element) {
+        const name = element.getAttribute('data-wasm');
+        const packed = window.htmlpacker?.glue?.[name];
+        if (packed) {
+            return packed;
+        }
+        const global = element.getAttribute('data-global') || 'wasm_bindgen';
+        // let bindings of classic scripts never show up on window
+        const glue = new Function(
+            `return typeof ${global} === 'undefined' ? undefined : ${global};`
+        )();
+        if (typeof glue !== 'function') {
+            throw new Error(`No wasm-bindgen glue '${global}' for ${element.id}`);
+        }
+        return glue;
+    }
+
+    // decode, cache and start one wasm app, then call its entry
+    async function loadApp(db, wasmAppElement, statusCallback) {
+        const name = wasmAppElement.getAttribute('data-wasm') || 'app';
+        console.log(`Loading wasm app ${name}...`);
         statusCallback?.("Loading main application...");
         
-        let wasmAppElement = document.getElementById('bin-wasm-app');
         const wasmAppHash = wasmAppElement.getAttribute('hash');
         let wasmBytes;
 
         if (!wasmAppHash) {
             console.log("No hash found for app, can't access indexedDB.");
             statusCallback?.("Decompressing application (no cache)...");
+            wasmBytes = await decodeBlob(wasmAppElement);
         } else {
             console.log(wasmAppHash);
-            const cacheKey = `wasm-app-${wasmAppHash}`;
+            const cacheKey = `wasm-${name}-${wasmAppHash}`;
             
             statusCallback?.("Checking cache for application...");
             const cachedBytes = await getAssetFromCache(db, cacheKey);
@@ -131,11 +269,8 @@ window.addEventListener('DOMContentLoaded', runApp);
             } else {
                 console.log("App module not found in cache. Decoding...");
                 statusCallback?.("Decoding application data...");
-                const wasmApp64 = wasmAppElement.innerHTML.trim();
-                const b = b64ToBytes(wasmApp64);
-                
                 statusCallback?.("Decompressing application...");
-                wasmBytes = await wasm_decoder.decompress(b);
+                wasmBytes = await decodeBlob(wasmAppElement);
                 checkMagicBytes(wasmBytes);
                 
                 statusCallback?.("Caching application...");
@@ -148,8 +283,19 @@ window.addEventListener('DOMContentLoaded', runApp);
         }
 
         statusCallback?.("Initializing application...");
-        await wasm_bindgen(wasmBytes);
+        const glue = getGlue(wasmAppElement);
+        await glue(wasmBytes);
+
+        const entry = wasmAppElement.getAttribute('data-entry');
+        if (entry) {
+            if (typeof glue[entry] !== 'function') {
+                throw new Error(`wasm app '${name}' has no exported function '${entry}'`);
+            }
+            const args = JSON.parse(wasmAppElement.getAttribute('data-args') || '[]');
+            await glue[entry](...args);
+        }
         statusCallback?.("Application initialized");
+        return glue;
     }
     
     // IndexedDB constants
@@ -207,6 +353,94 @@ window.addEventListener('DOMContentLoaded', runApp);
         });
     }
     
+    // the database and decoder are set up once
+    // either by the app or by the first asset that needs them
+    let dbReady = null;
+    let decoderReady = null;
+
+    function getDb(statusCallback) {
+        if (!dbReady) {
+            dbReady = openDb(statusCallback);
+        }
+        return dbReady;
+    }
+
+    function ensureDecoder(statusCallback) {
+        if (!decoderReady) {
+            if (!document.getElementById('bin-wasm-decoder')) {
+                decoderReady = Promise.resolve();
+            } else {
+                decoderReady = getDb(statusCallback)
+                    .then(db => loadDecoder(db, statusCallback));
+            }
+        }
+        return decoderReady;
+    }
+
+    // plain base64 blobs can be decoded without the wasm decoder
+    // outside of wasm mode so can base122 and what the browser decompresses
+    function needsDecoder(element) {
+        const encoding = element.getAttribute('encoding') || 'base64';
+        const compression = element.getAttribute('compression') || 'none';
+        const mode = decoderMode();
+        if (mode === 'wasm') {
+            return encoding !== 'base64' || compression !== 'none';
+        }
+        return mode === 'auto' && compression !== 'none' && !hasNative(compression);
+    }
+
+    // runtime api for the assets: section
+    // every asset is decoded once and kept in memory
+    const assetBytes = new Map();
+    const assetUrls = new Map();
+
+    function getAssetElement(id) {
+        const element = document.getElementById(id);
+        if (!element || !element.hasAttribute('data-asset')) {
+            throw new Error(`No packed asset with id '${id}'`);
+        }
+        return element;
+    }
+
+    async function getBytes(id) {
+        if (!assetBytes.has(id)) {
+            const element = getAssetElement(id);
+            const decoded = (async () => {
+                if (needsDecoder(element)) {
+                    await ensureDecoder();
+                }
+                return decodeBlob(element);
+            })();
+            assetBytes.set(id, decoded);
+            // let a failed decode be retried
+            decoded.catch(() => assetBytes.delete(id));
+        }
+        return assetBytes.get(id);
+    }
+
+    async function getText(id) {
+        const bytes = await getBytes(id);
+        return new TextDecoder().decode(bytes);
+    }
+
+    async function getObjectURL(id) {
+        if (!assetUrls.has(id)) {
+            const element = getAssetElement(id);
+            const mime = element.getAttribute('mime') || 'application/octet-stream';
+            const url = getBytes(id)
+                .then(bytes => URL.createObjectURL(new Blob([bytes], { type: mime })));
+            assetUrls.set(id, url);
+            url.catch(() => assetUrls.delete(id));
+        }
+        return assetUrls.get(id);
+    }
+
+    window.htmlpacker = Object.assign(window.htmlpacker || {}, {
+        getBytes,
+        getText,
+        getObjectURL,
+    });
+
     // Main setup function exposed globally
     window.setupWasm = async function(loadingScreen) {
         // Create a status callback function if loadingScreen is provided
@@ -218,9 +452,31 @@ window.addEventListener('DOMContentLoaded', runApp);
             console.log("Setting up WASM application...");
             updateStatus("Initializing...");
             
-            const db = await openDb(updateStatus);
-            await loadDecoder(db, updateStatus);
-            await loadApp(db, updateStatus);
+            const db = await getDb(updateStatus);
+
+            // apps are already in start order, dependencies first
+            // pages packed before apps had names only have bin-wasm-app
+            let apps = Array.from(document.querySelectorAll('pre[data-wasm]'));
+            const legacyApp = document.getElementById('bin-wasm-app');
+            if (apps.length === 0 && legacyApp) {
+                apps = [legacyApp];
+            }
+            if (apps.length === 0) {
+                console.log("No wasm app in this page, skipping.");
+            }
+            if (apps.some(needsDecoder)) {
+                await ensureDecoder(updateStatus);
+            }
+
+            const started = window.htmlpacker.apps = window.htmlpacker.apps || {};
+            for (const app of apps) {
+                const name = app.getAttribute('data-wasm') || 'app';
+                // with several apps the status says which one it is about
+                const appStatus = apps.length > 1 ?
+                    (text) => updateStatus(`${text} (${name})`) :
+                    updateStatus;
+                started[name] = await loadApp(db, app, appStatus);
+            }
             
             console.log("WASM module initialized successfully!");
         } catch (e) {
@@ -0,0 +1,13 @@
+use wasm_bindgen::prelude::*;
+
+// the entry in config.yaml, called by the runtime with its args
+#[wasm_bindgen]
+pub fn start(name: &str) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("no document")?;
+    if let Some(app) = document.get_element_by_id("app") {
+        app.set_inner_html(&format!("<h1>hello from {}</h1>", name));
+    }
+    Ok(())
+}
@@ -4,11 +4,17 @@
 * tiny bin entry point
 */
 
-use std::error::Error;
+use std::process::ExitCode;
 use htmlpacker::*;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    packer::run().await
+async fn main() -> ExitCode {
+    match packer::run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
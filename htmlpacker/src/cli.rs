@@ -5,15 +5,16 @@
 * yaml config declaration
 */
 
-use std::error::Error;
 use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-use clap::{Parser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::config::*;
+use crate::error::PackError;
+use crate::fetcher;
 
 // yaml structs
 // not sure if this is correct
@@ -25,12 +26,26 @@ pub struct YamlRoot {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct YamlPack {
     pub runtime: Option<YamlRuntime>,
+    // local path or remote url of an existing index.html
+    pub entry: Option<String>,
     pub meta: Option<YamlMeta>,
-    pub favicon: Option<YamlAssets>,
+    pub favicon: Option<YamlFavicon>,
     pub css: Option<YamlAssets>,
     pub html: Option<YamlAssets>,
     pub scripts: Option<YamlAssets>,
-    pub wasm: Option<HashMap<String, YamlWasmModule>>,
+    // sorted by key, the same config always packs the same way
+    pub wasm: Option<BTreeMap<String, YamlWasmModule>>,
+    pub assets: Option<Vec<YamlBinaryAsset>>,
+    pub fonts: Option<Vec<YamlFont>>,
+    pub fetch: Option<YamlFetch>,
+    pub budgets: Option<YamlBudgets>,
+    // same as --reproducible
+    #[serde(default)]
+    pub reproducible: bool,
+    #[serde(default)]
+    pub self_extracting: bool,
+    #[serde(default)]
+    pub minify: MinifyConfig,
 }
 
 // 
@@ -42,8 +57,21 @@ pub struct YamlRuntime {
     pub icon: bool,
     #[serde(default = "default_true")]
     pub core: bool,
-    #[serde(default = "default_true")]
-    pub decoder: bool,
+    #[serde(default = "default_decoder")]
+    pub decoder: YamlDecoder,
+}
+
+// wasm, native or auto
+// true and false from before the modes still mean wasm and no decoder
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum YamlDecoder {
+    Enabled(bool),
+    Mode(String),
+}
+
+fn default_decoder() -> YamlDecoder {
+    YamlDecoder::Enabled(true)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,12 +80,92 @@ pub struct YamlMeta {
     pub author: Option<String>,
     pub description: Option<String>,
     pub keywords: Option<String>,
+    pub lang: Option<String>,
+    pub theme_color: Option<String>,
+    pub url: Option<String>,
+    pub site_name: Option<String>,
+    pub og_type: Option<String>,
+    pub twitter_card: Option<String>,
+    pub twitter_site: Option<String>,
+    // local path or remote url
+    pub image: Option<String>,
+    pub image_alt: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct YamlAssets {
     pub local: Option<Vec<String>>,
-    pub remote: Option<Vec<String>>,
+    pub remote: Option<Vec<YamlRemote>>,
+}
+
+// a remote entry is a plain url, or a url pinned to its contents
+//   - https://cdn.example.com/lib.js
+//   - url: https://cdn.example.com/lib.js
+//     integrity: sha256-...
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum YamlRemote {
+    Url(String),
+    Pinned {
+        url: String,
+        integrity: Option<String>,
+    },
+}
+
+impl YamlRemote {
+    pub fn url(&self) -> &str {
+        match self {
+            YamlRemote::Url(url) | YamlRemote::Pinned { url, .. } => url,
+        }
+    }
+
+    pub fn integrity(&self) -> Option<&str> {
+        match self {
+            YamlRemote::Url(_) => None,
+            YamlRemote::Pinned { integrity, .. } => integrity.as_deref(),
+        }
+    }
+}
+
+// remote fetch cache and lockfile
+// the lockfile defaults to htmlpacker.lock next to the config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlFetch {
+    #[serde(default)]
+    pub offline: bool,
+    pub concurrency: Option<usize>,
+    // seconds
+    pub timeout: Option<u64>,
+    pub retries: Option<u32>,
+    pub cache_dir: Option<String>,
+    pub lockfile: Option<String>,
+}
+
+// size limits, in bytes or like "25 MB"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlBudgets {
+    pub total: Option<YamlSize>,
+    pub asset: Option<YamlSize>,
+    // by name in the size report, like wasm.app
+    #[serde(default)]
+    pub assets: BTreeMap<String, YamlSize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum YamlSize {
+    Bytes(u64),
+    Text(String),
+}
+
+// favicon sources plus the option to generate the full set
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlFavicon {
+    #[serde(flatten)]
+    pub sources: YamlAssets,
+    #[serde(default)]
+    pub generate: bool,
+    pub mask_color: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,8 +174,60 @@ pub struct YamlWasmModule {
     pub compile_wasm: bool,
     pub id: String,
     pub path: String,
+    // brotli, gzip, deflate, zstd, auto or none
+    #[serde(default = "default_compression")]
+    pub compression: String,
+    // codec tuning, the codec's defaults when left out
+    pub quality: Option<u32>,
+    pub window: Option<u32>,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    // the module's wasm-bindgen js, packed into its own namespace
+    pub glue: Option<String>,
+    // the global name the glue was built with, defaults to wasm_bindgen
+    pub global: Option<String>,
+    // apps start by order, then by name
+    #[serde(default)]
+    pub order: i64,
+    // keys of the modules that have to start first
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    // exported function to call after init, with args
+    pub entry: Option<String>,
+    #[serde(default)]
+    pub args: Vec<serde_yaml::Value>,
+}
+
+// path is a local path or a remote url
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlBinaryAsset {
+    pub id: String,
+    pub path: String,
+    pub mime: Option<String>,
     #[serde(default = "default_compression")]
     pub compression: String,
+    pub quality: Option<u32>,
+    pub window: Option<u32>,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+}
+
+// weight can be written as 400 or "bold"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlFont {
+    pub family: String,
+    pub path: String,
+    pub weight: Option<serde_yaml::Value>,
+    pub style: Option<String>,
+    pub display: Option<String>,
+    pub subset: Option<YamlFontSubset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlFontSubset {
+    pub chars: Option<String>,
+    #[serde(default)]
+    pub used: bool,
 }
 
 fn default_true() -> bool {
@@ -82,25 +242,153 @@ fn default_compression() -> String {
     "none".to_string()
 }
 
+fn default_encoding() -> String {
+    "base64".to_string()
+}
+
 // clap  
+// `htmlpacker config.yaml` still works next to the subcommands,
+// it is the same as `htmlpacker build config.yaml`
 #[derive(Parser)]
 #[command(name = "htmlpacker")]
 #[command(about = "Pack web assets into a single HTML file")]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
     /// path to the YAML configuration file
-    pub config: PathBuf,
+    pub config: Option<PathBuf>,
     
     /// output file path (defaults to ./index.html)
     #[arg(short, long, default_value = "./index.html")]
     pub output: PathBuf,
+
+    /// fetch remote entries without integrity and write their sha256 into the config
+    #[arg(long)]
+    pub pin: bool,
+
+    /// build only from the fetch cache and lockfile, never touch the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// only use remote assets pinned in the lockfile, never rewrite it,
+    /// and date the output file with SOURCE_DATE_EPOCH
+    #[arg(long, global = true)]
+    pub reproducible: bool,
+
+    #[command(flatten)]
+    pub report: ReportOptions,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// where the bytes went, printed after every build and pack
+#[derive(Args)]
+pub struct ReportOptions {
+    /// how to show the size report
+    #[arg(long = "report", global = true, value_enum, default_value_t = ReportFormat::Table)]
+    pub format: ReportFormat,
+
+    /// also write the size report as json to this file
+    #[arg(long = "report-file", global = true)]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    None,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// pack a YAML config into one html file
+    Build {
+        /// path to the YAML configuration file
+        config: PathBuf,
+
+        /// output file path (defaults to ./index.html)
+        #[arg(short, long, default_value = "./index.html")]
+        output: PathBuf,
+
+        /// fetch remote entries without integrity and write their sha256 into the config
+        #[arg(long)]
+        pin: bool,
+    },
+    /// scaffold a commented config and an example wasm crate
+    Init {
+        /// directory to create the project in
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// overwrite files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// check a config and the files it points at, without packing
+    Validate {
+        /// path to the YAML configuration file
+        config: PathBuf,
+    },
+    /// show what a packed html file is made of
+    Inspect {
+        /// the packed html file
+        file: PathBuf,
+    },
+    /// decode and verify every blob of a packed html file and write the pieces out
+    Unpack {
+        /// the packed html file
+        file: PathBuf,
+
+        /// directory to write the pieces to
+        #[arg(short, long, default_value = "./unpacked")]
+        dir: PathBuf,
+    },
+    /// pack an existing html document, no YAML needed
+    Pack {
+        /// html document used as the template, e.g. dist/index.html
+        entry: PathBuf,
+
+        /// output file path (defaults to ./index.html)
+        #[arg(short, long, default_value = "./index.html")]
+        output: PathBuf,
+
+        /// inject the htmlpacker runtime (loading screen and decoder)
+        #[arg(long)]
+        runtime: bool,
+    },
+    /// rebuild on every change and serve the page with live reload
+    Watch {
+        /// path to the YAML configuration file
+        config: PathBuf,
+
+        /// output file path (defaults to ./index.html)
+        #[arg(short, long, default_value = "./index.html")]
+        output: PathBuf,
+
+        /// port of the preview server on 127.0.0.1
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// pack a config twice in reproducible mode and compare the results
+    Check {
+        /// path to the YAML configuration file
+        config: PathBuf,
+
+        /// a distributed html file that has to match the packed output
+        #[arg(long)]
+        expect: Option<PathBuf>,
+    },
 }
 
 
 // convert our parsed yaml data into internal config data
 pub async fn set_config_from_yaml(
     pack: YamlPack
-) -> Result<PackerConfig, Box<dyn Error>> {
+) -> Result<PackerConfig, PackError> {
     let mut config = PackerConfig::default();
+    // url -> integrity of every pinned remote entry
+    let mut integrity = BTreeMap::new();
 
     // runtime.enabled is like a master switch
     if let Some(runtime) = pack.runtime {
@@ -109,55 +397,338 @@ pub async fn set_config_from_yaml(
                 enabled: true,
                 icon: runtime.icon,
                 core: runtime.core,
-                decoder: runtime.decoder,
+                decoder: convert_decoder(&runtime.decoder)?,
             }
         } else {
             RuntimeConfig {
                 enabled: false,
                 icon: false,
                 core: false,
-                decoder: false,
+                decoder: DecoderMode::Native,
             }
         }
     }
 
-    config.meta = pack.meta.map(|m| MetaConfig {
-        title: m.title,
-        author: m.author,
-        description: m.description,
-        keywords: m.keywords,
-    });
+    config.entry = pack.entry.map(|entry| convert_source(&entry));
+    config.reproducible = pack.reproducible;
+    config.self_extracting = pack.self_extracting;
+    config.minify = pack.minify;
+
+    if let Some(budgets) = pack.budgets {
+        let mut assets = BTreeMap::new();
+        for (name, size) in budgets.assets {
+            let limit = convert_size(&size, &format!("budgets.assets.{}", name))?;
+            assets.insert(name, limit);
+        }
+        config.budgets = Some(BudgetConfig {
+            total: budgets.total.map(|size| convert_size(&size, "budgets.total")).transpose()?,
+            asset: budgets.asset.map(|size| convert_size(&size, "budgets.asset")).transpose()?,
+            assets,
+        });
+    }
+
+    config.meta = match pack.meta {
+        Some(m) => Some(MetaConfig {
+            title: m.title,
+            author: m.author,
+            description: m.description,
+            keywords: m.keywords,
+            lang: m.lang,
+            theme_color: m.theme_color,
+            url: m.url,
+            site_name: m.site_name,
+            og_type: m.og_type,
+            twitter_card: m.twitter_card,
+            twitter_site: m.twitter_site,
+            image: m.image.map(|image| convert_source(&image)),
+            image_alt: m.image_alt,
+        }),
+        None => None,
+    };
 
     // simple assets conversion
-    config.favicon = convert_yaml_assets(pack.favicon)?;   
-    config.styles = convert_yaml_assets(pack.css)?;
-    config.html = convert_yaml_assets(pack.html)?;
-    config.scripts = convert_yaml_assets(pack.scripts)?;
+    if let Some(favicon) = pack.favicon {
+        if favicon.generate {
+            let mut set = FaviconSetConfig::default();
+            if let Some(mask_color) = favicon.mask_color {
+                set.mask_color = mask_color;
+            }
+            config.favicon_set = Some(set);
+        }
+        config.favicon = convert_yaml_assets(
+            Some(favicon.sources),
+            "favicon",
+            &mut integrity,
+        )?;
+    }
+    config.styles = convert_yaml_assets(pack.css, "css", &mut integrity)?;
+    config.html = convert_yaml_assets(pack.html, "html", &mut integrity)?;
+    config.scripts = convert_yaml_assets(pack.scripts, "scripts", &mut integrity)?;
 
     // wasm modules from hashmap to vec
-    config.wasm = pack.wasm.map(|wasm_map| {
-        wasm_map.into_iter()
-            .map(|(_key, module)| WasmModule {
+    // every module in the map is an app the runtime starts
+    if let Some(wasm_map) = pack.wasm {
+        let mut modules = Vec::new();
+        for (key, module) in wasm_map {
+            let global = module.global.unwrap_or_else(|| "wasm_bindgen".to_string());
+            if !is_js_identifier(&global) {
+                return Err(PackError::ConfigParse {
+                    path: None,
+                    key: Some(format!("wasm.{}.global", key)),
+                    message: format!("'{}' is not a javascript identifier", global),
+                });
+            }
+            let mut args = Vec::new();
+            for (i, arg) in module.args.into_iter().enumerate() {
+                let arg = serde_json::to_value(arg).map_err(|e| PackError::ConfigParse {
+                    path: None,
+                    key: Some(format!("wasm.{}.args[{}]", key, i)),
+                    message: format!("cannot pass argument to javascript: {}", e),
+                })?;
+                args.push(arg);
+            }
+            let app = WasmApp {
+                glue: module.glue.map(|glue| convert_source(&glue)),
+                global,
+                order: module.order,
+                depends_on: module.depends_on,
+                entry: module.entry,
+                args,
+                name: key.clone(),
+            };
+            let compression = convert_compression(
+                &module.compression,
+                &format!("wasm.{}.compression", key),
+            )?;
+            modules.push(WasmModule {
                 compile_wasm: module.compile_wasm,
-                id: module.id,
-                source: AssetSource::Local(PathBuf::from(module.path)),
+                source: convert_source(&module.path),
                 //compression: CompressionType::Brotli, 
-                compression: match module.compression.as_str() {
-                    "brotli" => CompressionType::Brotli,
-                    "none" => CompressionType::None,
-                    _ => CompressionType::None,
-                }
-            })
-            .collect()
-    });
+                compression,
+                level: convert_level(
+                    compression,
+                    module.quality,
+                    module.window,
+                    &format!("wasm.{}", key),
+                )?,
+                encoding: convert_encoding(
+                    &module.encoding,
+                    &format!("wasm.{}.encoding", key),
+                )?,
+                id: module.id,
+                app: Some(app),
+            });
+        }
+        config.wasm = Some(modules);
+    }
+
+    // binary assets keep their order from the yaml list
+    if let Some(assets) = pack.assets {
+        let mut binary_assets = Vec::new();
+        for asset in assets {
+            let key = format!("assets.{}", asset.id);
+            let compression = convert_compression(
+                &asset.compression,
+                &format!("{}.compression", key),
+            )?;
+            binary_assets.push(BinaryAsset {
+                source: convert_source(&asset.path),
+                mime: asset.mime,
+                compression,
+                level: convert_level(compression, asset.quality, asset.window, &key)?,
+                encoding: convert_encoding(
+                    &asset.encoding,
+                    &format!("{}.encoding", key),
+                )?,
+                id: asset.id,
+            });
+        }
+        config.assets = Some(binary_assets);
+    }
+
+    if let Some(fonts) = pack.fonts {
+        let mut font_configs = Vec::new();
+        for (i, font) in fonts.into_iter().enumerate() {
+            let weight = match font.weight {
+                None => None,
+                Some(serde_yaml::Value::Number(n)) => Some(n.to_string()),
+                Some(serde_yaml::Value::String(s)) => Some(s),
+                Some(_) => return Err(PackError::ConfigParse {
+                    path: None,
+                    key: Some(format!("fonts[{}].weight", i)),
+                    message: "font weight must be a number or a string".to_string(),
+                }),
+            };
+            font_configs.push(FontConfig {
+                family: font.family,
+                source: convert_source(&font.path),
+                weight,
+                style: font.style,
+                display: font.display,
+                subset: font.subset.map(|s| FontSubset {
+                    chars: s.chars,
+                    used: s.used,
+                }),
+            });
+        }
+        config.fonts = Some(font_configs);
+    }
+
+    if let Some(fetch) = pack.fetch {
+        let defaults = FetchConfig::default();
+        if fetch.concurrency == Some(0) {
+            return Err(PackError::ConfigParse {
+                path: None,
+                key: Some("fetch.concurrency".to_string()),
+                message: "concurrency must be at least 1".to_string(),
+            });
+        }
+        config.fetch = FetchConfig {
+            offline: fetch.offline,
+            concurrency: fetch.concurrency.unwrap_or(defaults.concurrency),
+            timeout: fetch.timeout.unwrap_or(defaults.timeout),
+            retries: fetch.retries.unwrap_or(defaults.retries),
+            cache_dir: fetch.cache_dir.map(PathBuf::from),
+            lockfile: fetch.lockfile.map(PathBuf::from),
+            integrity,
+            ..defaults
+        };
+    } else {
+        config.fetch.integrity = integrity;
+    }
 
     Ok(config)
 }
 
+fn convert_compression(
+    compression: &str,
+    key: &str,
+) -> Result<CompressionType, PackError> {
+    CompressionType::from_name(compression).ok_or_else(|| PackError::ConfigParse {
+        path: None,
+        key: Some(key.to_string()),
+        message: format!(
+            "unknown compression '{}', expected brotli, gzip, deflate, zstd, auto or none",
+            compression,
+        ),
+    })
+}
+
+// quality and window have to fit the codec
+// auto clamps them to each codec it tries instead
+fn convert_level(
+    compression: CompressionType,
+    quality: Option<u32>,
+    window: Option<u32>,
+    key: &str,
+) -> Result<CompressionLevel, PackError> {
+    let level = CompressionLevel { quality, window };
+    if compression == CompressionType::Auto {
+        return Ok(level);
+    }
+    let check = |value: Option<u32>, range: Option<(u32, u32)>, name: &str| {
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let message = match range {
+            Some((min, max)) if value < min || value > max => format!(
+                "{} {} is out of range for {}, expected {} to {}",
+                name, value, compression.as_str(), min, max,
+            ),
+            Some(_) => return Ok(()),
+            None => format!("{} cannot be set for {}", name, compression.as_str()),
+        };
+        Err(PackError::ConfigParse {
+            path: None,
+            key: Some(format!("{}.{}", key, name)),
+            message,
+        })
+    };
+    check(quality, compression.quality_range(), "quality")?;
+    check(window, compression.window_range(), "window")?;
+    Ok(level)
+}
+
+fn convert_encoding(
+    encoding: &str,
+    key: &str,
+) -> Result<EncodingType, PackError> {
+    match encoding {
+        "base64" => Ok(EncodingType::Base64),
+        "base122" => Ok(EncodingType::Base122),
+        other => Err(PackError::ConfigParse {
+            path: None,
+            key: Some(key.to_string()),
+            message: format!(
+                "unknown encoding '{}', expected base64 or base122", other
+            ),
+        }),
+    }
+}
+
+// true and false from before the modes, the wasm decoder or none
+fn convert_decoder(decoder: &YamlDecoder) -> Result<DecoderMode, PackError> {
+    let mode = match decoder {
+        YamlDecoder::Enabled(true) => return Ok(DecoderMode::Wasm),
+        YamlDecoder::Enabled(false) => return Ok(DecoderMode::Native),
+        YamlDecoder::Mode(mode) => mode,
+    };
+    DecoderMode::from_name(mode).ok_or_else(|| PackError::ConfigParse {
+        path: None,
+        key: Some("runtime.decoder".to_string()),
+        message: format!("unknown decoder '{}', expected wasm, native or auto", mode),
+    })
+}
+
+// 1048576, "512 KB", "25MB" or "1.5 GB", units of 1024 like the size report
+fn convert_size(size: &YamlSize, key: &str) -> Result<u64, PackError> {
+    let text = match size {
+        YamlSize::Bytes(bytes) => return Ok(*bytes),
+        YamlSize::Text(text) => text.trim(),
+    };
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let unit = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => Some(1),
+        "KB" | "K" | "KIB" => Some(1 << 10),
+        "MB" | "M" | "MIB" => Some(1 << 20),
+        "GB" | "G" | "GIB" => Some(1 << 30),
+        _ => None,
+    };
+    match (number.parse::<f64>(), unit) {
+        (Ok(number), Some(unit)) => Ok((number * unit as f64) as u64),
+        _ => Err(PackError::ConfigParse {
+            path: None,
+            key: Some(key.to_string()),
+            message: format!("'{}' is not a size, expected bytes or something like 25 MB", text),
+        }),
+    }
+}
+
+// the glue global ends up in generated javascript
+fn is_js_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+// a single string that is either a remote url or a local path
+fn convert_source(source: &str) -> AssetSource {
+    match Url::parse(source) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            AssetSource::Remote(url)
+        }
+        _ => AssetSource::Local(PathBuf::from(source)),
+    }
+}
+
 // from YamlAsset strings to specific AssetSource
+// pinned remote entries have their integrity collected on the way
 fn convert_yaml_assets(
-    assets: Option<YamlAssets>
-) -> Result<Option<Vec<AssetSource>>, Box<dyn Error>> {
+    assets: Option<YamlAssets>,
+    section: &str,
+    integrity: &mut BTreeMap<String, String>,
+) -> Result<Option<Vec<AssetSource>>, PackError> {
     match assets {
         None => Ok(None),
         Some(a) => {
@@ -173,9 +744,24 @@ fn convert_yaml_assets(
             
             // convert remote urls
             if let Some(remote) = a.remote {
-                for url_str in remote {
-                    let url = Url::parse(&url_str)
-                        .map_err(|e| format!("Invalid URL '{}': {}", url_str, e))?;
+                for (i, entry) in remote.into_iter().enumerate() {
+                    let key = format!("{}.remote[{}]", section, i);
+                    let url_str = entry.url();
+                    let url = Url::parse(url_str)
+                        .map_err(|e| PackError::ConfigParse {
+                            path: None,
+                            key: Some(key.clone()),
+                            message: format!("invalid URL '{}': {}", url_str, e),
+                        })?;
+                    if let Some(value) = entry.integrity() {
+                        fetcher::check_integrity(value)
+                            .map_err(|message| PackError::ConfigParse {
+                                path: None,
+                                key: Some(format!("{}.integrity", key)),
+                                message,
+                            })?;
+                        integrity.insert(url.to_string(), value.to_string());
+                    }
                     sources.push(AssetSource::Remote(url));
                 }
             }
@@ -6,18 +6,28 @@
 * the config determines how the sources get packed into html
 */
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use url::Url;
 use serde::{Deserialize, Serialize};
 
 // internal config structs
 
 // enum that distinguishes between local and remote files
+// inline holds contents handed over directly through the library api
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum AssetSource {
     Local(PathBuf),
     Remote(Url),
+    #[serde(skip)]
+    Inline(Vec<u8>),
+}
+
+impl AssetSource {
+    pub fn inline(contents: impl Into<Vec<u8>>) -> Self {
+        AssetSource::Inline(contents.into())
+    }
 }
 
 impl Default for AssetSource {
@@ -26,14 +36,118 @@ impl Default for AssetSource {
     }
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 //#[serde(rename_all = "lowercase")]
 pub enum CompressionType {
     Brotli,
+    Gzip,
+    // zlib wrapped, what DecompressionStream calls deflate
+    Deflate,
+    Zstd,
+    // every codec is tried, the smallest result is packed
+    // never written into a page, the blob records the codec that won
+    Auto,
     #[default]
     None,
 }
 
+impl CompressionType {
+    // the codecs auto picks from, none first so ties need no decoder
+    pub const CODECS: [CompressionType; 5] = [
+        CompressionType::None,
+        CompressionType::Brotli,
+        CompressionType::Zstd,
+        CompressionType::Gzip,
+        CompressionType::Deflate,
+    ];
+
+    // what DecompressionStream handles in every browser
+    pub const NATIVE_CODECS: [CompressionType; 3] = [
+        CompressionType::None,
+        CompressionType::Gzip,
+        CompressionType::Deflate,
+    ];
+
+    // the name written into the html and read back by the runtime
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionType::Brotli => "brotli",
+            CompressionType::Gzip => "gzip",
+            CompressionType::Deflate => "deflate",
+            CompressionType::Zstd => "zstd",
+            CompressionType::Auto => "auto",
+            CompressionType::None => "none",
+        }
+    }
+
+    // and back, from the yaml or a packed file
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "brotli" => Some(CompressionType::Brotli),
+            "gzip" => Some(CompressionType::Gzip),
+            "deflate" => Some(CompressionType::Deflate),
+            "zstd" => Some(CompressionType::Zstd),
+            "auto" => Some(CompressionType::Auto),
+            "none" => Some(CompressionType::None),
+            _ => None,
+        }
+    }
+
+    // the quality range of the codec, the default sits inside it
+    pub fn quality_range(&self) -> Option<(u32, u32)> {
+        match self {
+            CompressionType::Brotli => Some((0, 11)),
+            CompressionType::Gzip | CompressionType::Deflate => Some((0, 9)),
+            CompressionType::Zstd => Some((1, 22)),
+            CompressionType::Auto | CompressionType::None => None,
+        }
+    }
+
+    // log2 of the window size, gzip and deflate always use 15
+    pub fn window_range(&self) -> Option<(u32, u32)> {
+        match self {
+            CompressionType::Brotli => Some((10, 24)),
+            CompressionType::Zstd => Some((10, 27)),
+            _ => None,
+        }
+    }
+}
+
+// how hard a blob is compressed, codec defaults when unset
+// with auto, each codec gets the setting clamped to its own range
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompressionLevel {
+    pub quality: Option<u32>,
+    pub window: Option<u32>,
+}
+
+// binary to text encoding of a <pre> blob
+// base64 costs 33%, base122 about 14% but needs the decoder at runtime
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EncodingType {
+    #[default]
+    Base64,
+    Base122,
+}
+
+impl EncodingType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EncodingType::Base64 => "base64",
+            EncodingType::Base122 => "base122",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "base64" => Some(EncodingType::Base64),
+            "base122" => Some(EncodingType::Base122),
+            _ => None,
+        }
+    }
+}
+
 //impl Default for CompressionType {
 //    fn default() -> Self {
 //        CompressionType::None
@@ -46,7 +160,7 @@ impl Default for RuntimeConfig {
             enabled: true,
             icon: true,
             core: true,
-            decoder: true,
+            decoder: DecoderMode::Wasm,
         }
     }
 }
@@ -57,12 +171,164 @@ impl Default for RuntimeConfig {
 pub struct PackerConfig {
     #[serde(default)]
     pub runtime: RuntimeConfig,
+    // an existing html document used as the page skeleton
+    // when set, meta is left to the document itself
+    pub entry: Option<AssetSource>,
     pub meta: Option<MetaConfig>,
     pub favicon: Option<Vec<AssetSource>>,
+    pub favicon_set: Option<FaviconSetConfig>,
     pub styles: Option<Vec<AssetSource>>,
     pub scripts: Option<Vec<AssetSource>>,
     pub html: Option<Vec<AssetSource>>,
     pub wasm: Option<Vec<WasmModule>>,
+    pub assets: Option<Vec<BinaryAsset>>,
+    pub fonts: Option<Vec<FontConfig>>,
+    #[serde(default)]
+    pub fetch: FetchConfig,
+    // same sources, same bytes: remote assets only from the lockfile,
+    // the output file dated with SOURCE_DATE_EPOCH
+    #[serde(default)]
+    pub reproducible: bool,
+    // styles, scripts and fragments compressed into one payload
+    // that a bootstrap script unpacks when the page opens
+    #[serde(default)]
+    pub self_extracting: bool,
+    #[serde(default)]
+    pub minify: MinifyConfig,
+    // size limits checked against the report of every pack
+    pub budgets: Option<BudgetConfig>,
+}
+
+impl PackerConfig {
+    // every local file this config reads, with the key it came from
+    pub fn local_files(&self) -> Vec<(String, &Path)> {
+        let mut sources: Vec<(String, &AssetSource)> = vec![];
+        let lists = [
+            ("favicon", &self.favicon),
+            ("css", &self.styles),
+            ("scripts", &self.scripts),
+            ("html", &self.html),
+        ];
+        // the yaml keeps local and remote entries in separate lists
+        for (section, list) in lists {
+            let local = list.iter().flatten().filter(|source| matches!(source, AssetSource::Local(_)));
+            for (i, source) in local.enumerate() {
+                sources.push((format!("{}.local[{}]", section, i), source));
+            }
+        }
+        sources.extend(self.entry.iter().map(|source| ("entry".to_string(), source)));
+        sources.extend(self.meta.iter()
+            .filter_map(|meta| meta.image.as_ref())
+            .map(|source| ("meta.image".to_string(), source)));
+        for module in self.wasm.iter().flatten() {
+            let name = module.app.as_ref().map_or(&module.id, |app| &app.name);
+            sources.push((format!("wasm.{}.path", name), &module.source));
+            if let Some(glue) = module.app.as_ref().and_then(|app| app.glue.as_ref()) {
+                sources.push((format!("wasm.{}.glue", name), glue));
+            }
+        }
+        for asset in self.assets.iter().flatten() {
+            sources.push((format!("assets.{}", asset.id), &asset.source));
+        }
+        for (i, font) in self.fonts.iter().flatten().enumerate() {
+            sources.push((format!("fonts[{}]", i), &font.source));
+        }
+        sources.into_iter()
+            .filter_map(|(key, source)| match source {
+                AssetSource::Local(path) => Some((key, path.as_path())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // blobs whose codec the native decoder cannot count on, by config key
+    pub fn native_codec_conflicts(&self) -> Vec<(String, CompressionType)> {
+        if !self.runtime.enabled || self.runtime.decoder != DecoderMode::Native {
+            return vec![];
+        }
+        let mut blobs: Vec<(String, CompressionType)> = vec![];
+        for module in self.wasm.iter().flatten() {
+            let name = module.app.as_ref().map_or(&module.id, |app| &app.name);
+            blobs.push((format!("wasm.{}.compression", name), module.compression));
+        }
+        for asset in self.assets.iter().flatten() {
+            blobs.push((format!("assets.{}.compression", asset.id), asset.compression));
+        }
+        blobs.into_iter()
+            .filter(|(_, compression)| {
+                *compression != CompressionType::Auto
+                    && !CompressionType::NATIVE_CODECS.contains(compression)
+            })
+            .collect()
+    }
+}
+
+// size limits in bytes, measured as what each asset adds to the document
+// an asset can have its own limit, keyed by its name in the size report
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    // the whole file
+    pub total: Option<u64>,
+    // any single asset without a limit of its own
+    pub asset: Option<u64>,
+    // report name -> limit, like wasm.app or assets.earth-texture
+    pub assets: BTreeMap<String, u64>,
+}
+
+// how remote assets are fetched
+// everything fetched lands in a content addressed cache,
+// the lockfile pins each url to the sha256 of what it served
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    // build only from the cache and lockfile, never touch the network
+    pub offline: bool,
+    // remote assets must be pinned by the lockfile or an integrity,
+    // the lockfile is read but never written
+    pub frozen: bool,
+    // how many files are read or fetched at the same time
+    pub concurrency: usize,
+    // per request, in seconds
+    pub timeout: u64,
+    // extra attempts after a timeout, connection error or 5xx
+    pub retries: u32,
+    // defaults to ~/.cache/htmlpacker
+    pub cache_dir: Option<PathBuf>,
+    // no lockfile is read or written when unset
+    pub lockfile: Option<PathBuf>,
+    // url -> subresource integrity, like sha256-<base64>
+    pub integrity: BTreeMap<String, String>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            offline: false,
+            frozen: false,
+            concurrency: 8,
+            timeout: 30,
+            retries: 3,
+            cache_dir: None,
+            lockfile: None,
+            integrity: BTreeMap::new(),
+        }
+    }
+}
+
+// when set, the first favicon is turned into the full set
+// .ico, png sizes, apple-touch-icon and mask icon
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FaviconSetConfig {
+    pub mask_color: String,
+}
+
+impl Default for FaviconSetConfig {
+    fn default() -> Self {
+        FaviconSetConfig {
+            mask_color: "#000000".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -70,15 +336,74 @@ pub struct RuntimeConfig {
     pub enabled: bool,
     pub icon: bool,
     pub core: bool,
-    pub decoder: bool,
+    pub decoder: DecoderMode,
 }
 
+// what gets minified on the way into the page, the runtime included
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MinifyConfig {
+    pub js: bool,
+    pub css: bool,
+    pub html: bool,
+}
+
+// how the runtime gets blobs back to bytes
+// wasm ships the decoder, native leaves it out and uses DecompressionStream,
+// auto ships it too but only loads it when the browser cannot do the job
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DecoderMode {
+    #[default]
+    Wasm,
+    Native,
+    Auto,
+}
+
+impl DecoderMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecoderMode::Wasm => "wasm",
+            DecoderMode::Native => "native",
+            DecoderMode::Auto => "auto",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wasm" => Some(DecoderMode::Wasm),
+            "native" => Some(DecoderMode::Native),
+            "auto" => Some(DecoderMode::Auto),
+            _ => None,
+        }
+    }
+
+    // the codecs compression: auto may pick from
+    pub fn codecs(&self) -> &'static [CompressionType] {
+        match self {
+            DecoderMode::Native => &CompressionType::NATIVE_CODECS,
+            _ => &CompressionType::CODECS,
+        }
+    }
+}
+
+// everything that ends up as meta tags in the head
+// title and description double as opengraph/twitter card defaults
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct MetaConfig {
     pub title: Option<String>,
     pub author: Option<String>,
     pub description: Option<String>,
     pub keywords: Option<String>,
+    pub lang: Option<String>,
+    pub theme_color: Option<String>,
+    pub url: Option<String>,
+    pub site_name: Option<String>,
+    pub og_type: Option<String>,
+    pub twitter_card: Option<String>,
+    pub twitter_site: Option<String>,
+    // preview image, embedded into the page as a data uri
+    pub image: Option<AssetSource>,
+    pub image_alt: Option<String>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -87,4 +412,82 @@ pub struct WasmModule {
     pub id: String,
     pub source: AssetSource,
     pub compression: CompressionType,
+    #[serde(default)]
+    pub level: CompressionLevel,
+    #[serde(default)]
+    pub encoding: EncodingType,
+    // how the runtime starts it
+    // none for blobs it never starts by itself, like the decoder
+    #[serde(default)]
+    pub app: Option<WasmApp>,
+}
+
+// one wasm application on the page
+// apps start one after another, by order then name,
+// an app always starts after the apps it depends on
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WasmApp {
+    // the key in the wasm map, htmlpacker.apps[name] at runtime
+    pub name: String,
+    // wasm-bindgen --target no-modules js, packed into its own scope
+    // without it the glue has to be loaded as a script, as a global
+    pub glue: Option<AssetSource>,
+    // the global the glue defines, --no-modules-global
+    pub global: String,
+    pub order: i64,
+    pub depends_on: Vec<String>,
+    // exported function called once the module is initialized
+    pub entry: Option<String>,
+    pub args: Vec<serde_json::Value>,
+}
+
+impl Default for WasmApp {
+    fn default() -> Self {
+        WasmApp {
+            name: String::new(),
+            glue: None,
+            global: "wasm_bindgen".to_string(),
+            order: 0,
+            depends_on: vec![],
+            entry: None,
+            args: vec![],
+        }
+    }
+}
+
+// any file shipped as a <pre> blob and loaded at runtime through
+// htmlpacker.getBytes(id), getText(id) or getObjectURL(id)
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BinaryAsset {
+    pub id: String,
+    pub source: AssetSource,
+    // guessed from the bytes or file name when not set
+    pub mime: Option<String>,
+    pub compression: CompressionType,
+    #[serde(default)]
+    pub level: CompressionLevel,
+    #[serde(default)]
+    pub encoding: EncodingType,
+}
+
+// a font inlined into the styles as an @font-face rule
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct FontConfig {
+    pub family: String,
+    pub source: AssetSource,
+    pub weight: Option<String>,
+    pub style: Option<String>,
+    pub display: Option<String>,
+    pub subset: Option<FontSubset>,
+}
+
+// which glyphs survive subsetting, both options can be combined
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct FontSubset {
+    // keep exactly these characters
+    pub chars: Option<String>,
+    // keep the characters used in the packed html fragments
+    #[serde(default)]
+    pub used: bool,
 }
@@ -0,0 +1,594 @@
+/*
+* css.rs
+*
+* make a stylesheet self contained
+* every url(...) is fetched and turned into a data uri
+* every @import is fetched and inlined in place, recursively
+* references are resolved against the stylesheet's own path or url
+*/
+
+use std::path::{Component, Path, PathBuf};
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use url::Url;
+
+use crate::config::AssetSource;
+use crate::encoder;
+use crate::error::PackError;
+use crate::fetcher::Fetcher;
+
+// where a stylesheet (or anything else) was loaded from
+// relative references are resolved against it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Origin {
+    Local(PathBuf),
+    Remote(Url),
+    // inline text from the library api, relative to the working dir
+    Inline,
+}
+
+impl Origin {
+    pub fn of(source: &AssetSource) -> Origin {
+        match source {
+            AssetSource::Local(path) => Origin::Local(path.clone()),
+            AssetSource::Remote(url) => Origin::Remote(url.clone()),
+            AssetSource::Inline(_) => Origin::Inline,
+        }
+    }
+
+    // the origin of a reference found inside this file
+    // None for things that are not files: data uris, fragments, etc.
+    pub fn resolve(&self, reference: &str) -> Option<Origin> {
+        let reference = reference.trim();
+        if reference.is_empty()
+            || reference.starts_with('#')
+            || reference.starts_with("data:")
+            || reference.starts_with("blob:")
+            || reference.starts_with("about:")
+            || reference.starts_with("javascript:")
+            || reference.starts_with("mailto:")
+        {
+            return None;
+        }
+        // absolute urls are fetched no matter where we are
+        if let Ok(url) = Url::parse(reference) {
+            return match url.scheme() {
+                "http" | "https" => Some(Origin::Remote(url)),
+                "file" => url.to_file_path().ok().map(Origin::Local),
+                _ => None,
+            };
+        }
+        match self {
+            Origin::Remote(base) => base.join(reference).ok().map(Origin::Remote),
+            Origin::Local(_) | Origin::Inline if reference.starts_with("//") => {
+                Url::parse(&format!("https:{}", reference)).ok().map(Origin::Remote)
+            }
+            Origin::Local(path) => {
+                // drop any query or fragment, they mean nothing on disk
+                let file = reference.split(['?', '#']).next().unwrap_or(reference);
+                let file = percent_decode(file);
+                // /assets/x.png is relative to the site root,
+                // take the closest parent directory that has it
+                if let Some(rooted) = file.strip_prefix('/') {
+                    let found = path.ancestors()
+                        .skip(1)
+                        .map(|dir| dir.join(rooted))
+                        .find(|candidate| candidate.exists());
+                    if let Some(found) = found {
+                        return Some(Origin::Local(normalize(&found)));
+                    }
+                }
+                let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+                Some(Origin::Local(normalize(&dir.join(file))))
+            }
+            Origin::Inline => {
+                let file = reference.split(['?', '#']).next().unwrap_or(reference);
+                Some(Origin::Local(normalize(Path::new(&percent_decode(file)))))
+            }
+        }
+    }
+
+    // file name used to guess the mime type
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Origin::Local(path) => path.to_str().map(String::from),
+            Origin::Remote(url) => Some(url.path().to_string()),
+            Origin::Inline => None,
+        }
+    }
+
+    pub async fn fetch(&self, fetcher: &Fetcher) -> Result<Vec<u8>, PackError> {
+        match self {
+            Origin::Local(path) => fetcher.get_local_bytes(path).await,
+            Origin::Remote(url) => fetcher.get_remote_bytes(url).await,
+            Origin::Inline => Err(PackError::Unsupported {
+                key: None,
+                message: "cannot fetch inline contents".to_string(),
+            }),
+        }
+    }
+
+    // a data uri with the mime type guessed from the bytes and name
+    pub async fn fetch_data_uri(&self, fetcher: &Fetcher) -> Result<String, PackError> {
+        let buffer = self.fetch(fetcher).await?;
+        let mime = encoder::sniff_mime(&buffer, self.name().as_deref());
+        Ok(encoder::data_uri(&buffer, mime))
+    }
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Local(path) => write!(f, "{}", path.display()),
+            Origin::Remote(url) => write!(f, "{}", url),
+            Origin::Inline => write!(f, "<inline>"),
+        }
+    }
+}
+
+// a stylesheet cut into what we copy and what we replace
+enum Token<'a> {
+    Text(&'a str),
+    // the whole url(...) and the reference inside it
+    Url(&'a str, String),
+    // the whole @import rule, up to and including its ;
+    Import(&'a str),
+}
+
+// inline every url() and @import of a stylesheet
+// anything that cannot be fetched is left as is, with a warning
+pub async fn inline_css(
+    css: &str,
+    origin: &Origin,
+    fetcher: &Fetcher,
+) -> Result<String, PackError> {
+    inline_css_rec(css, origin, fetcher, vec![origin.clone()]).await
+}
+
+// ancestors is the import chain that led here, to catch cycles
+// everything a stylesheet references is fetched at the same time
+async fn inline_css_rec(
+    css: &str,
+    origin: &Origin,
+    fetcher: &Fetcher,
+    ancestors: Vec<Origin>,
+) -> Result<String, PackError> {
+    let tokens = tokenize(css);
+    let replacements = stream::iter(&tokens)
+        .map(|token| {
+            let ancestors = &ancestors;
+            async move {
+                match token {
+                    Token::Text(_) => Ok(None),
+                    Token::Url(original, reference) => {
+                        Ok(inline_url(original, reference, origin, fetcher).await)
+                    }
+                    Token::Import(rule) => {
+                        inline_import(rule, origin, fetcher, ancestors).await.map(Some)
+                    }
+                }
+            }
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect::<Vec<Option<String>>>()
+        .await?;
+
+    let mut out = String::with_capacity(css.len());
+    for (token, replacement) in tokens.iter().zip(replacements) {
+        match (token, replacement) {
+            (_, Some(replacement)) => out.push_str(&replacement),
+            (Token::Text(text) | Token::Url(text, _) | Token::Import(text), None) => {
+                out.push_str(text)
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn tokenize(css: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    // start of the text not yet in a token
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while let Some(c) = css[i..].chars().next() {
+        let rest = &css[i..];
+        // comments are copied as is, so are strings
+        let special = if let Some(comment) = rest.strip_prefix("/*") {
+            i += comment.find("*/").map(|end| end + 4).unwrap_or(rest.len());
+            None
+        } else if c == '"' || c == '\'' {
+            i += string_end(rest);
+            None
+        } else if starts_with_ignore_case(rest, "@import") && !ident_char_before(&css[..i]) {
+            let len = import_len(rest);
+            Some((len, Token::Import(&rest[..len])))
+        } else if starts_with_ignore_case(rest, "url(") && !ident_char_before(&css[..i]) {
+            let (len, reference) = parse_url_token(rest);
+            Some((len, Token::Url(&rest[..len], reference)))
+        } else {
+            i += c.len_utf8();
+            None
+        };
+        if let Some((len, token)) = special {
+            if text_start < i {
+                tokens.push(Token::Text(&css[text_start..i]));
+            }
+            tokens.push(token);
+            i += len;
+            text_start = i;
+        }
+    }
+    if text_start < css.len() {
+        tokens.push(Token::Text(&css[text_start..]));
+    }
+    tokens
+}
+
+// a data uri in place of the reference, None to keep it as is
+async fn inline_url(
+    original: &str,
+    reference: &str,
+    origin: &Origin,
+    fetcher: &Fetcher,
+) -> Option<String> {
+    let target = origin.resolve(reference)?;
+    match target.fetch_data_uri(fetcher).await {
+        Ok(data_uri) => Some(format!("url(\"{}\")", data_uri)),
+        Err(e) => {
+            eprintln!("⚠️  cannot inline {} in {}: {}", original, origin, e);
+            None
+        }
+    }
+}
+
+// the rule runs up to the first ; outside of strings and parens
+fn import_len(rule: &str) -> usize {
+    let mut len = "@import".len();
+    let mut depth = 0;
+    while let Some(c) = rule[len..].chars().next() {
+        match c {
+            '"' | '\'' => {
+                len += string_end(&rule[len..]);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth <= 0 => return len + 1,
+            _ => (),
+        }
+        len += c.len_utf8();
+    }
+    len
+}
+
+// the stylesheet an @import rule points to, inlined in its place
+async fn inline_import(
+    rule: &str,
+    origin: &Origin,
+    fetcher: &Fetcher,
+    ancestors: &[Origin],
+) -> Result<String, PackError> {
+    let original = rule.to_string();
+    let body = rule["@import".len()..].trim_end_matches(';').trim();
+    let (reference, condition) = if starts_with_ignore_case(body, "url(") {
+        let (url_len, reference) = parse_url_token(body);
+        (reference, body[url_len..].trim())
+    } else if body.starts_with('"') || body.starts_with('\'') {
+        let end = string_end(body);
+        (unquote(&body[..end]), body[end..].trim())
+    } else {
+        eprintln!("⚠️  cannot parse {} in {}", original.trim(), origin);
+        return Ok(original);
+    };
+
+    // layers and supports() would need more than a @media wrapper
+    let lower = condition.to_ascii_lowercase();
+    if lower.starts_with("layer") || lower.starts_with("supports(") {
+        eprintln!("⚠️  cannot inline conditional {} in {}", original.trim(), origin);
+        return Ok(original);
+    }
+
+    let Some(target) = origin.resolve(&reference) else {
+        return Ok(original);
+    };
+    // only a loop back up the import chain is circular
+    // the same file imported from two places is inlined twice, like a browser would
+    if ancestors.contains(&target) {
+        eprintln!("⚠️  skipping circular @import of {} in {}", target, origin);
+        return Ok(String::new());
+    }
+
+    let text = match target.fetch(fetcher).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) => {
+            eprintln!("⚠️  cannot inline @import {} in {}: {}", reference, origin, e);
+            return Ok(original);
+        }
+    };
+    println!("Inlining @import {}", target);
+    let mut chain = ancestors.to_vec();
+    chain.push(target.clone());
+    let inlined = Box::pin(inline_css_rec(&text, &target, fetcher, chain)).await?;
+
+    Ok(if condition.is_empty() {
+        format!("\n{}\n", inlined)
+    } else {
+        format!("\n@media {} {{\n{}\n}}\n", condition, inlined)
+    })
+}
+
+// url( ... ) starting at the beginning of text
+// returns the token length and the unquoted reference
+fn parse_url_token(text: &str) -> (usize, String) {
+    let inner_start = "url(".len();
+    let inner = &text[inner_start..];
+    let trimmed = inner.trim_start();
+    let skipped = inner.len() - trimmed.len();
+    if trimmed.starts_with('"') || trimmed.starts_with('\'') {
+        let end = string_end(trimmed);
+        let reference = unquote(&trimmed[..end]);
+        let close = trimmed[end..].find(')').map(|i| end + i + 1).unwrap_or(trimmed.len());
+        (inner_start + skipped + close, reference)
+    } else {
+        // an unquoted url can escape its ) and other chars with a backslash
+        let mut reference = String::new();
+        let mut chars = inner.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                ')' => return (inner_start + i + 1, reference.trim().to_string()),
+                '\\' => reference.extend(chars.next().map(|(_, escaped)| escaped)),
+                _ => reference.push(c),
+            }
+        }
+        (text.len(), reference.trim().to_string())
+    }
+}
+
+// length of a quoted string at the start of text, quotes included
+fn string_end(text: &str) -> usize {
+    let quote = text.chars().next().unwrap_or('"');
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote || c == '\n' {
+            return i + c.len_utf8();
+        }
+    }
+    text.len()
+}
+
+fn unquote(text: &str) -> String {
+    let inner = text.get(1..text.len().saturating_sub(1)).unwrap_or("");
+    inner.replace("\\\"", "\"").replace("\\'", "'")
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .map(|start| start.eq_ignore_ascii_case(prefix))
+        .unwrap_or(false)
+}
+
+// so that things like my-url( are not taken for url(
+fn ident_char_before(before: &str) -> bool {
+    before.chars()
+        .next_back()
+        .map(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        .unwrap_or(false)
+}
+
+// fold ./ and ../ so that the same file always gets the same path
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            // a leading .. has nothing to cancel out and stays
+            Component::ParentDir => {
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) {
+                    out.pop();
+                } else {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+// %20 and friends in local file references
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = text.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::FetchConfig;
+
+    // a tiny png, only its data uri matters
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    // a temp dir with files in it, removed again on drop
+    struct Site(PathBuf);
+
+    impl Site {
+        fn new(test: &str, files: &[(&str, &[u8])]) -> Site {
+            let dir = std::env::temp_dir()
+                .join(format!("htmlpacker-css-{}-{}", test, std::process::id()));
+            for (name, contents) in files {
+                let path = dir.join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Site(dir)
+        }
+
+        fn origin(&self, name: &str) -> Origin {
+            Origin::Local(self.0.join(name))
+        }
+
+        // offline, so a remote url fails instead of going out
+        async fn inline(&self, name: &str) -> String {
+            let fetcher = Fetcher::new(&FetchConfig {
+                offline: true,
+                cache_dir: Some(self.0.join("cache")),
+                ..Default::default()
+            }).unwrap();
+            let css = fs::read_to_string(self.0.join(name)).unwrap();
+            inline_css(&css, &self.origin(name), &fetcher).await.unwrap()
+        }
+    }
+
+    impl Drop for Site {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // the references tokenize found, in order
+    fn references(css: &str) -> Vec<String> {
+        tokenize(css).into_iter()
+            .filter_map(|token| match token {
+                Token::Url(_, reference) => Some(reference),
+                Token::Import(rule) => Some(rule.to_string()),
+                Token::Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_finds_urls_and_imports() {
+        let css = "@import \"a.css\";\np { background: url(b.png) URL( \"c d.png\" ) url('e.png'); }";
+        assert_eq!(references(css), vec!["@import \"a.css\";", "b.png", "c d.png", "e.png"]);
+        // every byte ends up in exactly one token
+        let joined: String = tokenize(css).into_iter()
+            .map(|token| match token {
+                Token::Text(text) | Token::Url(text, _) | Token::Import(text) => text,
+            })
+            .collect();
+        assert_eq!(joined, css);
+    }
+
+    #[test]
+    fn tokenize_skips_strings_and_comments() {
+        let css = "/* url(a.png) @import \"x.css\"; */\n\
+            p::after { content: \"url(b.png)\"; }\n\
+            q::after { content: 'it\\'s url(c.png)'; }\n\
+            r { --my-url(d.png); background: url(e.png); }";
+        assert_eq!(references(css), vec!["e.png"]);
+    }
+
+    #[test]
+    fn unquoted_urls_can_escape_parens() {
+        assert_eq!(parse_url_token("url(a\\).png) rest"), ("url(a\\).png)".len(), "a).png".to_string()));
+        assert_eq!(parse_url_token("url( \"b).png\" )"), ("url( \"b).png\" )".len(), "b).png".to_string()));
+        assert_eq!(parse_url_token("url(open"), ("url(open".len(), "open".to_string()));
+    }
+
+    #[test]
+    fn resolve_relative_rooted_and_skipped() {
+        let site = Site::new("resolve", &[("img/a.png", PNG), ("css/deep/x.css", b"")]);
+        let origin = site.origin("css/deep/x.css");
+        assert_eq!(origin.resolve("../b.png"), Some(site.origin("css/b.png")));
+        // rooted at the closest parent that has the file
+        assert_eq!(origin.resolve("/img/a.png?v=2"), Some(site.origin("img/a.png")));
+        assert_eq!(origin.resolve("data:image/png;base64,AAAA"), None);
+        assert_eq!(origin.resolve("#filter"), None);
+        assert_eq!(
+            origin.resolve("https://example.com/a.png"),
+            Some(Origin::Remote(Url::parse("https://example.com/a.png").unwrap())),
+        );
+        let remote = Origin::Remote(Url::parse("https://example.com/css/x.css").unwrap());
+        assert_eq!(
+            remote.resolve("../img/a.png"),
+            Some(Origin::Remote(Url::parse("https://example.com/img/a.png").unwrap())),
+        );
+    }
+
+    #[tokio::test]
+    async fn urls_become_data_uris() {
+        let site = Site::new("urls", &[
+            ("a.png", PNG),
+            ("style.css", b"a { background: url(a.png); } b { background: url( 'a.png' ); }"),
+        ]);
+        let css = site.inline("style.css").await;
+        let data_uri = encoder::data_uri(PNG, "image/png");
+        assert_eq!(css, format!(
+            "a {{ background: url(\"{0}\"); }} b {{ background: url(\"{0}\"); }}", data_uri,
+        ));
+    }
+
+    #[tokio::test]
+    async fn data_and_unreachable_urls_stay() {
+        let css = "a { background: url(data:image/png;base64,AAAA); } \
+            b { background: url(https://example.com/b.png); } \
+            c { background: url(missing.png); }";
+        let site = Site::new("stay", &[("style.css", css.as_bytes())]);
+        assert_eq!(site.inline("style.css").await, css);
+    }
+
+    #[tokio::test]
+    async fn imports_are_inlined_recursively() {
+        let site = Site::new("imports", &[
+            ("style.css", b"@import \"parts/a.css\";\n@import url(b.css) print;\nbody {}"),
+            ("parts/a.css", b"@import 'deep/c.css'; a {}"),
+            ("parts/deep/c.css", b"c { background: url(../../x.png); }"),
+            ("b.css", b"b {}"),
+            ("x.png", PNG),
+        ]);
+        let css = site.inline("style.css").await;
+        assert!(!css.contains("@import"), "{}", css);
+        assert!(css.contains(&encoder::data_uri(PNG, "image/png")), "{}", css);
+        assert!(css.contains("@media print {\nb {}\n}"), "{}", css);
+        // in the order they were imported
+        let order: Vec<usize> = ["c {", "a {}", "b {}", "body {}"].iter()
+            .map(|part| css.find(part).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{}", css);
+    }
+
+    #[tokio::test]
+    async fn circular_imports_are_cut() {
+        let site = Site::new("cycle", &[
+            ("a.css", b"@import \"b.css\"; a {}"),
+            ("b.css", b"@import \"a.css\"; b {}"),
+        ]);
+        let css = site.inline("a.css").await;
+        assert_eq!(css, "\n b {}\n a {}");
+    }
+
+    #[test]
+    fn normalize_folds_dots() {
+        assert_eq!(normalize(Path::new("a/./b/../c.css")), Path::new("a/c.css"));
+        assert_eq!(normalize(Path::new("a/../../x.css")), Path::new("../x.css"));
+    }
+
+    #[test]
+    fn normalize_keeps_leading_parent_dirs() {
+        assert_eq!(normalize(Path::new("../../x.css")), Path::new("../../x.css"));
+        assert_eq!(normalize(Path::new("./../a/../../x.css")), Path::new("../../x.css"));
+    }
+}
@@ -6,30 +6,57 @@
 * return a Base, which means what goes into a <pre> tag inside the html
 * 
 * base64 is highly optimized for encode/decode
+* base122 packs 7 bits into each byte of utf-8, less overhead
 * we will eventually implement base94 if there is a need
 * 
 * so what do we need to do to encode
 * we read the file from a filepath
 * 
-* we compress with brotli (or not)
+* we compress with brotli, gzip, deflate or zstd (or not)
+* auto tries every codec and keeps the smallest
 * 
-* we encode as base64
+* we encode as base64 (or base122)
 * 
 * we return the id and the utf-8 compatible string
 */
 
 use std::fs::File;
-use std::io::Read;
-use std::error::Error;
+use std::io;
+use std::io::{Read, Write};
+use std::path::PathBuf;
 use base64::prelude::*;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
 
 use sha2::{Sha256, Digest};
 
+use crate::config::{CompressionLevel, CompressionType, EncodingType};
+use crate::error::PackError;
+
 #[derive(Debug)]
 pub struct Base {
     pub id: String, // identifier
     pub hash: String, //sha-256 hash of the text as bytes
     pub text: String, // text content
+    pub compression: CompressionType, // how the bytes were compressed
+    pub encoding: EncodingType, // how the bytes were turned into text
+    pub mime: Option<String>, // only set for binary assets
+    pub app: Option<AppInfo>, // only set for wasm apps
+    pub raw_size: usize, // bytes before compression, for the size report
+    pub compressed_size: usize, // bytes after compression, before encoding
+}
+
+// what the runtime needs to start a wasm app blob
+// written out as data- attributes on the <pre>
+#[derive(Debug, Default)]
+pub struct AppInfo {
+    pub name: String,
+    // only when the glue was not packed into htmlpacker.glue
+    pub global: Option<String>,
+    pub entry: Option<String>,
+    // json array
+    pub args: Option<String>,
 }
 
 impl Base {
@@ -38,15 +65,94 @@ impl Base {
             id,
             hash,
             text,
+            compression: CompressionType::None,
+            encoding: EncodingType::Base64,
+            mime: None,
+            app: None,
+            raw_size: 0,
+            compressed_size: 0,
+        }
+    }
+}
+
+// guess the mime type of a buffer from its first bytes
+// falls back to the file extension, then to a plain byte stream
+pub fn sniff_mime(buffer: &[u8], name: Option<&str>) -> &'static str {
+    let magic: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"\x00asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"OTTO", "font/otf"),
+        (b"\x00\x01\x00\x00", "font/ttf"),
+        (b"%PDF", "application/pdf"),
+    ];
+    for (bytes, mime) in magic {
+        if buffer.starts_with(bytes) {
+            return mime;
         }
     }
+    if buffer.len() > 12 && &buffer[0..4] == b"RIFF" && &buffer[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    let extension = name
+        .and_then(|n| n.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("wasm") => "application/wasm",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("css") => "text/css",
+        Some("js") | Some("mjs") => "text/javascript",
+        Some("html") | Some("htm") => "text/html",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("xml") => "application/xml",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ if looks_like_svg(buffer) => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+// svg has no magic bytes, peek at the start of the text instead
+fn looks_like_svg(buffer: &[u8]) -> bool {
+    let head = &buffer[..buffer.len().min(512)];
+    String::from_utf8_lossy(head).contains("<svg")
+}
+
+// a ready to use data uri for any buffer
+pub fn data_uri(buffer: &[u8], mime: &str) -> String {
+    format!("data:{};base64,{}", mime, BASE64_STANDARD.encode(buffer))
 }
 
 // get file and read file content
-fn get_file_bytes(png_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut file = File::open(png_path)?;
+pub fn get_file_bytes(png_path: &str) -> Result<Vec<u8>, PackError> {
+    let read_error = |source| PackError::AssetRead {
+        path: PathBuf::from(png_path),
+        key: None,
+        source,
+    };
+    let mut file = File::open(png_path).map_err(read_error)?;
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    file.read_to_end(&mut buffer).map_err(read_error)?;
     Ok(buffer)
 }
 
@@ -54,35 +160,256 @@ fn get_file_bytes(png_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
 pub fn encode_base64(
     file_path: &str, 
     id: &str,
-) -> Result<Base, Box<dyn Error>> {
+) -> Result<Base, PackError> {
     let buffer = get_file_bytes(file_path)?;
-    let hash = Sha256::digest(&buffer);
+    encode_bytes_base64(&buffer, id)
+}
+
+// encode a buffer already in memory to base64
+pub fn encode_bytes_base64(
+    buffer: &[u8],
+    id: &str,
+) -> Result<Base, PackError> {
+    encode_bytes(buffer, id, CompressionType::None, EncodingType::Base64)
+}
+
+// hash, compress and encode a buffer in one go
+// the hash is always taken from the raw bytes
+pub fn encode_bytes(
+    buffer: &[u8],
+    id: &str,
+    compression: CompressionType,
+    encoding: EncodingType,
+) -> Result<Base, PackError> {
+    encode_bytes_with_level(
+        buffer,
+        id,
+        compression,
+        CompressionLevel::default(),
+        encoding,
+        &CompressionType::CODECS,
+    )
+}
+
+// the same with the codec tuned, the blob records the codec auto picked
+// auto only picks from codecs, the runtime has to be able to undo it
+pub fn encode_bytes_with_level(
+    buffer: &[u8],
+    id: &str,
+    compression: CompressionType,
+    level: CompressionLevel,
+    encoding: EncodingType,
+    codecs: &[CompressionType],
+) -> Result<Base, PackError> {
+    let hash = Sha256::digest(buffer);
     let hash_string = format!("{:x}", hash);
-    //println!("hash {:?}\nstring: {}", hash, hash_string);
-    let encoded = BASE64_STANDARD.encode(&buffer);
-    Ok(Base::new(
-        String::from(id),
-        hash_string,
-        encoded
-    ))
+    let (compression, compressed_buffer) = compress(buffer, compression, level, codecs)
+        .map_err(|source| PackError::Compression {
+            id: String::from(id),
+            source,
+        })?;
+    let encoded = match encoding {
+        EncodingType::Base64 => BASE64_STANDARD.encode(&compressed_buffer),
+        EncodingType::Base122 => encode_base122(&compressed_buffer),
+    };
+    let mut base = Base::new(String::from(id), hash_string, encoded);
+    base.compression = compression;
+    base.encoding = encoding;
+    base.raw_size = buffer.len();
+    base.compressed_size = compressed_buffer.len();
+    Ok(base)
+}
+
+// base122: https://github.com/kevinAlbs/Base122
+// read the input 7 bits at a time, each chunk becomes one utf-8 char
+// chunks that would be an illegal char in html (null, newline,
+// carriage return, double quote, ampersand, backslash) are folded
+// together with the next chunk into a single two byte char
+const BASE122_ILLEGALS: [u8; 6] = [0, 10, 13, 34, 38, 92];
+const BASE122_SHORTENED: u32 = 0b111;
+
+pub fn encode_base122(buffer: &[u8]) -> String {
+    let mut out = String::with_capacity(buffer.len() * 8 / 7 + 1);
+    let mut chunks = SevenBits::new(buffer);
+    while let Some(bits) = chunks.next() {
+        match BASE122_ILLEGALS.iter().position(|&i| i == bits) {
+            Some(illegal_index) => {
+                // 110iii1f 10ffffff, iii is the illegal char
+                // and f the 7 bits that come after it
+                let (index, next) = match chunks.next() {
+                    Some(next) => (illegal_index as u32, next),
+                    None => (BASE122_SHORTENED, bits),
+                };
+                let code = (0b10 << 6)
+                    | (index << 8)
+                    | (next as u32 & 0b0111_1111);
+                out.push(char::from_u32(code).expect("base122 char is below 0x800"));
+            }
+            None => out.push(bits as char),
+        }
+    }
+    out
+}
+
+// the inverse, same as the decoder crate does it in the browser
+pub fn decode_base122(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::<u8>::with_capacity(text.len() * 7 / 8);
+    let mut cur_byte: u8 = 0;
+    let mut bit_of_byte: u32 = 0;
+
+    let mut push7 = |bits: u8| {
+        let bits = bits << 1;
+        cur_byte |= bits >> bit_of_byte;
+        bit_of_byte += 7;
+        if bit_of_byte >= 8 {
+            out.push(cur_byte);
+            bit_of_byte -= 8;
+            cur_byte = ((bits as u32) << (7 - bit_of_byte)) as u8;
+        }
+    };
+
+    for c in text.chars() {
+        let c = c as u32;
+        if c > 127 {
+            let illegal_index = (c >> 8) & 0b111;
+            if illegal_index != BASE122_SHORTENED {
+                match BASE122_ILLEGALS.get(illegal_index as usize) {
+                    Some(&illegal) => push7(illegal),
+                    None => return Err(format!("invalid base122 char {:#x}", c)),
+                }
+            }
+            push7((c & 127) as u8);
+        } else if c != '\n' as u32 {
+            // newlines are never part of the data, only layout
+            push7(c as u8);
+        }
+    }
+    Ok(out)
+}
+
+// iterator over a byte buffer in 7 bit chunks
+// the last chunk is padded with zero bits
+struct SevenBits<'a> {
+    buffer: &'a [u8],
+    index: usize,
+    bit: u32,
+}
+
+impl<'a> SevenBits<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        SevenBits { buffer, index: 0, bit: 0 }
+    }
+}
+
+impl Iterator for SevenBits<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let first_byte = *self.buffer.get(self.index)?;
+        // bits [bit..bit+7] of the current byte, aligned to 7 bits
+        let first_part = (((0b1111_1110u32 >> self.bit) & first_byte as u32) << self.bit) >> 1;
+        self.bit += 7;
+        if self.bit < 8 {
+            return Some(first_part as u8);
+        }
+        // the rest comes from the start of the next byte
+        self.bit -= 8;
+        self.index += 1;
+        let second_part = match self.buffer.get(self.index) {
+            Some(&second_byte) => ((0xFF00u32 >> self.bit) & second_byte as u32 & 0xFF) >> (8 - self.bit),
+            None => 0,
+        };
+        Some((first_part | second_part) as u8)
+    }
 }
 
 // so we have a buffer of bytes
-// lets compress with brotli
+// lets compress with brotli (or gzip, deflate, zstd)
 // create a buffer for compressed data
+// the defaults are part of the reproducibility contract, together with
+// the codec versions in Cargo.lock: changing any changes every packed blob
+//const BROTLI_QUALITY: u32 = 11;   // quality (0-11)
+const BROTLI_QUALITY: u32 = 9;      // try 9 as sweet spot
+const BROTLI_WINDOW: u32 = 22;      // window size (recommended 20-22)
+const GZIP_QUALITY: u32 = 9;        // gzip and deflate, 0-9
+const ZSTD_QUALITY: u32 = 19;       // 1-22, past 19 needs a lot of memory
+
+// compress with one codec, or with auto the smallest of codecs
+// (none first, like CompressionType::CODECS)
+// returns the codec that was used
+pub fn compress(
+    buffer: &[u8],
+    compression: CompressionType,
+    level: CompressionLevel,
+    codecs: &[CompressionType],
+) -> io::Result<(CompressionType, Vec<u8>)> {
+    if compression == CompressionType::Auto {
+        let mut best = (CompressionType::None, buffer.to_vec());
+        for &codec in codecs.iter().skip(1) {
+            let (_, compressed) = compress(buffer, codec, level, codecs)?;
+            if compressed.len() < best.1.len() {
+                best = (codec, compressed);
+            }
+        }
+        println!("auto compression picked {}: {} -> {} bytes", best.0.as_str(), buffer.len(), best.1.len());
+        return Ok(best);
+    }
+
+    let clamp = |value: Option<u32>, range: Option<(u32, u32)>, default: u32| {
+        let value = value.unwrap_or(default);
+        range.map_or(value, |(min, max)| value.clamp(min, max))
+    };
+    let quality = |default| clamp(level.quality, compression.quality_range(), default);
+    let compressed = match compression {
+        CompressionType::Brotli => encode_brotli_with(
+            buffer,
+            quality(BROTLI_QUALITY),
+            clamp(level.window, compression.window_range(), BROTLI_WINDOW),
+        )?,
+        CompressionType::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(quality(GZIP_QUALITY)));
+            encoder.write_all(buffer)?;
+            encoder.finish()?
+        }
+        CompressionType::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(quality(GZIP_QUALITY)));
+            encoder.write_all(buffer)?;
+            encoder.finish()?
+        }
+        CompressionType::Zstd => {
+            let mut compressor = zstd::bulk::Compressor::new(quality(ZSTD_QUALITY) as i32)?;
+            // left to the quality level unless set
+            if let Some(window) = level.window {
+                let window = clamp(Some(window), compression.window_range(), window);
+                compressor.set_parameter(zstd::zstd_safe::CParameter::WindowLog(window))?;
+            }
+            compressor.compress(buffer)?
+        }
+        CompressionType::Auto | CompressionType::None => buffer.to_vec(),
+    };
+    Ok((compression, compressed))
+}
+
 pub fn encode_brotli(
     //buffer: &Vec<u8>
     buffer: &[u8]
-) -> Result<Vec<u8>, Box<dyn Error>> {
+) -> io::Result<Vec<u8>> {
+    encode_brotli_with(buffer, BROTLI_QUALITY, BROTLI_WINDOW)
+}
+
+fn encode_brotli_with(
+    buffer: &[u8],
+    quality: u32,
+    window: u32,
+) -> io::Result<Vec<u8>> {
     let mut compressed_buffer = Vec::new();
     println!("brotli compression"); 
     brotli::BrotliCompress(
         &mut &buffer[..],           // input buffer as a Read impl
         &mut compressed_buffer,     // output buffer as a Write impl
         &brotli::enc::BrotliEncoderParams {
-            //quality: 11,          // quality (0-11)
-            quality: 9,             // try 9 as sweet spot
-            lgwin: 22,              // Window size (recommended 20-22)
+            quality: quality as i32,
+            lgwin: window as i32,
             ..Default::default() 
         }
     )?;
@@ -90,20 +417,107 @@ pub fn encode_brotli(
     Ok(compressed_buffer)
 }
 
+pub fn decode_brotli(buffer: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed_buffer = Vec::new();
+    brotli::BrotliDecompress(&mut &buffer[..], &mut decompressed_buffer)?;
+    Ok(decompressed_buffer)
+}
+
+// blob text back to the raw bytes, undoing encode_bytes
+pub fn decode_bytes(
+    text: &str,
+    compression: CompressionType,
+    encoding: EncodingType,
+) -> Result<Vec<u8>, String> {
+    let decoded = match encoding {
+        EncodingType::Base64 => {
+            let text: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+            BASE64_STANDARD.decode(text).map_err(|e| e.to_string())?
+        }
+        EncodingType::Base122 => decode_base122(text)?,
+    };
+    let mut decompressed = Vec::new();
+    let result = match compression {
+        CompressionType::Brotli => decode_brotli(&decoded).map(|bytes| decompressed = bytes),
+        CompressionType::Gzip => GzDecoder::new(&decoded[..])
+            .read_to_end(&mut decompressed)
+            .map(|_| ()),
+        CompressionType::Deflate => ZlibDecoder::new(&decoded[..])
+            .read_to_end(&mut decompressed)
+            .map(|_| ()),
+        CompressionType::Zstd => zstd::stream::decode_all(&decoded[..])
+            .map(|bytes| decompressed = bytes),
+        CompressionType::Auto => return Err("auto is not a codec".to_string()),
+        CompressionType::None => return Ok(decoded),
+    };
+    result.map_err(|e| format!("{}: {}", compression.as_str(), e))?;
+    Ok(decompressed)
+}
+
 // encode both with brotli and then base64
 pub fn encode_brotli_base64(
     file_path: &str, 
     id: &str,
-) -> Result<Base, Box<dyn Error>> {
+) -> Result<Base, PackError> {
     let buffer = get_file_bytes(file_path)?;
-    let hash = Sha256::digest(&buffer);
-    let hash_string = format!("{:x}", hash);
-    let compressed_buffer = encode_brotli(&buffer)?;
-    let encoded = BASE64_STANDARD.encode(&compressed_buffer);
-    Ok(Base::new(
-        String::from(id),
-        hash_string,
-        encoded
-    ))
+    encode_bytes_brotli_base64(&buffer, id)
+}
+
+// same as above for a buffer already in memory
+pub fn encode_bytes_brotli_base64(
+    buffer: &[u8],
+    id: &str,
+) -> Result<Base, PackError> {
+    encode_bytes(buffer, id, CompressionType::Brotli, EncodingType::Base64)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(buffer: &[u8]) {
+        let text = encode_base122(buffer);
+        for illegal in BASE122_ILLEGALS {
+            assert!(!text.contains(illegal as char), "{:?} encodes to {:?}", buffer, text);
+        }
+        assert_eq!(decode_base122(&text).unwrap(), buffer, "{:?} as {:?}", buffer, text);
+    }
+
+    #[test]
+    fn base122_empty() {
+        assert_eq!(encode_base122(&[]), "");
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn base122_every_length_of_the_last_chunk() {
+        let buffer: Vec<u8> = (0..=20u32).map(|i| (i * 37) as u8).collect();
+        for len in 0..buffer.len() {
+            round_trip(&buffer[..len]);
+        }
+    }
+
+    #[test]
+    fn base122_illegal_chunks() {
+        for illegal in BASE122_ILLEGALS {
+            // the illegal bytes themselves
+            round_trip(&[illegal]);
+            round_trip(&[illegal, illegal]);
+            // an illegal 7 bit chunk, followed by more bits and at the very end
+            round_trip(&[illegal << 1, 0xff]);
+            round_trip(&[illegal << 1]);
+            round_trip(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, illegal]);
+        }
+    }
+
+    #[test]
+    fn base122_whitespace_at_the_edges() {
+        round_trip(b" \tpadded text\t ");
+        round_trip(b"\n\r\nlines\r\n");
+        // the first chunk is a space
+        let text = encode_base122(&[0x40, 0x40]);
+        assert!(text.starts_with(' '));
+        assert_eq!(decode_base122(&text).unwrap(), [0x40, 0x40]);
+    }
+}
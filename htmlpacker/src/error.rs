@@ -0,0 +1,193 @@
+/*
+* error.rs
+*
+* the one error type for everything that can go wrong while packing
+* each variant carries the path, url or module id that caused it
+* plus the config key when we know which yaml entry it came from
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum PackError {
+    // the yaml config could not be read from disk
+    ConfigRead {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // the yaml config is not valid, or holds an invalid value
+    ConfigParse {
+        path: Option<PathBuf>,
+        key: Option<String>,
+        message: String,
+    },
+    // a local asset could not be read
+    AssetRead {
+        path: PathBuf,
+        key: Option<String>,
+        source: io::Error,
+    },
+    // a remote asset could not be fetched, status is None if no response
+    RemoteFetch {
+        url: String,
+        status: Option<u16>,
+        key: Option<String>,
+        message: String,
+    },
+    // a remote asset did not match the integrity pinned in the config
+    Integrity {
+        url: String,
+        key: Option<String>,
+        expected: String,
+        actual: String,
+    },
+    // wasm-pack failed or the module layout is not what we expect
+    WasmBuild {
+        module_id: String,
+        message: String,
+    },
+    // compressing a binary failed
+    Compression {
+        id: String,
+        source: io::Error,
+    },
+    // bytes could not be turned into text, or text into bytes
+    Encoding {
+        id: String,
+        key: Option<String>,
+        message: String,
+    },
+    // something the packer does not handle (yet)
+    Unsupported {
+        key: Option<String>,
+        message: String,
+    },
+    // the packed document could not be written out
+    Output {
+        target: String,
+        source: io::Error,
+    },
+    // packing the same sources twice, or against a shipped file, differed
+    NotReproducible {
+        target: String,
+        message: String,
+    },
+    // a packed file that does not decode or verify
+    Unpack {
+        target: String,
+        message: String,
+    },
+    // an asset or the whole document is larger than its budget
+    OverBudget {
+        target: String,
+        size: usize,
+        limit: u64,
+        key: Option<String>,
+    },
+}
+
+impl PackError {
+    // attach the config key of the entry that failed
+    // keeps the first key if one was already set deeper down
+    pub fn with_key(mut self, config_key: &str) -> Self {
+        match &mut self {
+            PackError::ConfigParse { key, .. }
+            | PackError::AssetRead { key, .. }
+            | PackError::RemoteFetch { key, .. }
+            | PackError::Integrity { key, .. }
+            | PackError::Encoding { key, .. }
+            | PackError::Unsupported { key, .. }
+            | PackError::OverBudget { key, .. } if key.is_none() => {
+                *key = Some(config_key.to_string());
+            }
+            _ => (),
+        }
+        self
+    }
+
+    // the config key this error was raised for, if any
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            PackError::ConfigParse { key, .. }
+            | PackError::AssetRead { key, .. }
+            | PackError::RemoteFetch { key, .. }
+            | PackError::Integrity { key, .. }
+            | PackError::Encoding { key, .. }
+            | PackError::Unsupported { key, .. }
+            | PackError::OverBudget { key, .. } => key.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::ConfigRead { path, source } => write!(
+                f, "cannot read config {}: {}", path.display(), source
+            )?,
+            PackError::ConfigParse { path, message, .. } => match path {
+                Some(path) => write!(
+                    f, "invalid config {}: {}", path.display(), message
+                )?,
+                None => write!(f, "invalid config: {}", message)?,
+            },
+            PackError::AssetRead { path, source, .. } => write!(
+                f, "cannot read asset {}: {}", path.display(), source
+            )?,
+            PackError::RemoteFetch { url, status, message, .. } => match status {
+                Some(status) => write!(
+                    f, "cannot fetch {} (HTTP {}): {}", url, status, message
+                )?,
+                None => write!(f, "cannot fetch {}: {}", url, message)?,
+            },
+            PackError::Integrity { url, expected, actual, .. } => write!(
+                f, "integrity mismatch for {}\n  expected {}\n  actual   {}",
+                url, expected, actual,
+            )?,
+            PackError::WasmBuild { module_id, message } => write!(
+                f, "wasm build failed for module '{}': {}", module_id, message
+            )?,
+            PackError::Compression { id, source } => write!(
+                f, "cannot compress '{}': {}", id, source
+            )?,
+            PackError::Encoding { id, message, .. } => write!(
+                f, "cannot encode '{}': {}", id, message
+            )?,
+            PackError::Unsupported { message, .. } => write!(
+                f, "unsupported: {}", message
+            )?,
+            PackError::Output { target, source } => write!(
+                f, "cannot write output {}: {}", target, source
+            )?,
+            PackError::NotReproducible { target, message } => write!(
+                f, "{} is not reproducible: {}", target, message
+            )?,
+            PackError::Unpack { target, message } => write!(
+                f, "cannot unpack {}: {}", target, message
+            )?,
+            PackError::OverBudget { target, size, limit, .. } => write!(
+                f, "{} is {} bytes, over its budget of {} bytes", target, size, limit
+            )?,
+        }
+        if let Some(key) = self.key() {
+            write!(f, " (config key `{}`)", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PackError::ConfigRead { source, .. }
+            | PackError::AssetRead { source, .. }
+            | PackError::Compression { source, .. }
+            | PackError::Output { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
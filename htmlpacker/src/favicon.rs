@@ -0,0 +1,220 @@
+/*
+* favicon.rs
+*
+* turn favicon sources into the link tags that go into the head
+* either every source as is, with the right mime type and sizes
+* or the whole set generated from one svg or png:
+*   .ico (16, 32, 48), png 16/32/192/512, apple-touch-icon 180
+*   and a mask icon when the source is an svg
+*/
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use resvg::{tiny_skia, usvg};
+
+use crate::encoder;
+use crate::error::PackError;
+
+// png sizes that get their own <link rel="icon">
+const ICON_SIZES: [u32; 4] = [16, 32, 192, 512];
+// sizes packed together into the .ico
+const ICO_SIZES: [u32; 3] = [16, 32, 48];
+const APPLE_TOUCH_SIZE: u32 = 180;
+
+// one <link> in the head
+#[derive(Debug, Clone)]
+pub struct Icon {
+    pub rel: &'static str,
+    pub mime: &'static str,
+    pub sizes: Option<String>,
+    pub href: String,
+    // only used by the mask icon
+    pub color: Option<String>,
+}
+
+impl Icon {
+    // a favicon used as is
+    // raster images get their sizes read from the header
+    pub fn from_bytes(buffer: &[u8], name: Option<&str>) -> Icon {
+        let mime = encoder::sniff_mime(buffer, name);
+        let sizes = match mime {
+            "image/svg+xml" => Some("any".to_string()),
+            _ => image_dimensions(buffer).map(|(w, h)| format!("{}x{}", w, h)),
+        };
+        Icon {
+            rel: "icon",
+            mime,
+            sizes,
+            href: encoder::data_uri(buffer, mime),
+            color: None,
+        }
+    }
+}
+
+// generate the whole set from a single svg or raster source
+pub fn generate_set(
+    buffer: &[u8],
+    name: Option<&str>,
+    mask_color: &str,
+) -> Result<Vec<Icon>, PackError> {
+    let mime = encoder::sniff_mime(buffer, name);
+    let is_svg = mime == "image/svg+xml";
+    let source = if is_svg {
+        Source::Svg(Box::new(parse_svg(buffer)?))
+    } else {
+        Source::Raster(decode_raster(buffer)?)
+    };
+
+    let mut icons = vec![];
+
+    // .ico for the old browsers and anything asking for /favicon.ico
+    let mut frames = vec![];
+    for size in ICO_SIZES {
+        let png = encode_png(&source.render(size)?)?;
+        let frame = IcoFrame::with_encoded(png, size, size, image::ExtendedColorType::Rgba8)
+            .map_err(|e| icon_error(e.to_string()))?;
+        frames.push(frame);
+    }
+    let mut ico = vec![];
+    IcoEncoder::new(&mut ico)
+        .encode_images(&frames)
+        .map_err(|e| icon_error(e.to_string()))?;
+    icons.push(Icon {
+        rel: "icon",
+        mime: "image/x-icon",
+        sizes: Some(sizes_list(&ICO_SIZES)),
+        href: encoder::data_uri(&ico, "image/x-icon"),
+        color: None,
+    });
+
+    // modern browsers take the svg directly
+    if is_svg {
+        icons.push(Icon::from_bytes(buffer, name));
+    }
+
+    // png fallbacks, small ones for tabs, big ones for manifests/android
+    for size in ICON_SIZES {
+        let png = encode_png(&source.render(size)?)?;
+        icons.push(Icon {
+            rel: "icon",
+            mime: "image/png",
+            sizes: Some(format!("{}x{}", size, size)),
+            href: encoder::data_uri(&png, "image/png"),
+            color: None,
+        });
+    }
+
+    // iOS/macOS home screen
+    let png = encode_png(&source.render(APPLE_TOUCH_SIZE)?)?;
+    icons.push(Icon {
+        rel: "apple-touch-icon",
+        mime: "image/png",
+        sizes: Some(format!("{}x{}", APPLE_TOUCH_SIZE, APPLE_TOUCH_SIZE)),
+        href: encoder::data_uri(&png, "image/png"),
+        color: None,
+    });
+
+    // safari pinned tabs, needs a vector source
+    if is_svg {
+        icons.push(Icon {
+            rel: "mask-icon",
+            mime: "image/svg+xml",
+            sizes: None,
+            href: encoder::data_uri(buffer, "image/svg+xml"),
+            color: Some(mask_color.to_string()),
+        });
+    } else {
+        println!("Favicon source is not an svg, skipping mask icon.");
+    }
+
+    Ok(icons)
+}
+
+enum Source {
+    Svg(Box<usvg::Tree>),
+    Raster(DynamicImage),
+}
+
+impl Source {
+    // square rgba image of the given size
+    // non square sources are fitted in and centered
+    fn render(&self, size: u32) -> Result<RgbaImage, PackError> {
+        match self {
+            Source::Svg(tree) => {
+                let mut pixmap = tiny_skia::Pixmap::new(size, size)
+                    .ok_or_else(|| icon_error(format!("cannot allocate {}x{}", size, size)))?;
+                let svg_size = tree.size();
+                let scale = size as f32 / svg_size.width().max(svg_size.height());
+                let dx = (size as f32 - svg_size.width() * scale) / 2.0;
+                let dy = (size as f32 - svg_size.height() * scale) / 2.0;
+                let transform = tiny_skia::Transform::from_scale(scale, scale)
+                    .post_translate(dx, dy);
+                resvg::render(tree, transform, &mut pixmap.as_mut());
+                // tiny-skia works in premultiplied alpha
+                let pixels = pixmap.pixels()
+                    .iter()
+                    .flat_map(|p| {
+                        let c = p.demultiply();
+                        [c.red(), c.green(), c.blue(), c.alpha()]
+                    })
+                    .collect();
+                RgbaImage::from_raw(size, size, pixels)
+                    .ok_or_else(|| icon_error("svg render has the wrong size".to_string()))
+            }
+            Source::Raster(image) => {
+                let resized = image.resize(size, size, FilterType::Lanczos3).to_rgba8();
+                let mut canvas = RgbaImage::new(size, size);
+                let x = (size - resized.width()) / 2;
+                let y = (size - resized.height()) / 2;
+                image::imageops::overlay(&mut canvas, &resized, x as i64, y as i64);
+                Ok(canvas)
+            }
+        }
+    }
+}
+
+fn parse_svg(buffer: &[u8]) -> Result<usvg::Tree, PackError> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    usvg::Tree::from_data(buffer, &options)
+        .map_err(|e| icon_error(format!("cannot parse svg: {}", e)))
+}
+
+fn decode_raster(buffer: &[u8]) -> Result<DynamicImage, PackError> {
+    image::load_from_memory(buffer)
+        .map_err(|e| icon_error(format!("cannot decode image: {}", e)))
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, PackError> {
+    let mut png = Cursor::new(vec![]);
+    image.write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| icon_error(e.to_string()))?;
+    Ok(png.into_inner())
+}
+
+// width and height from the image header, without decoding it
+fn image_dimensions(buffer: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(buffer))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+fn sizes_list(sizes: &[u32]) -> String {
+    sizes.iter()
+        .map(|s| format!("{}x{}", s, s))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn icon_error(message: String) -> PackError {
+    PackError::Encoding {
+        id: "favicon".to_string(),
+        key: None,
+        message,
+    }
+}
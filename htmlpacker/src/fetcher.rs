@@ -2,67 +2,440 @@
 * fetcher.rs
 *
 * fetches source files whether they are local or external
+*
+* remote files go through the Fetcher during a pack:
+*   cache/blobs/<sha256>        the bytes, named by their own hash
+*   cache/urls/<sha256 of url>  the hash that url last served
+* and htmlpacker.lock pins every url to the hash it resolved to,
+* so a locked build never needs the network once the cache is warm
+*
+* downloads and local reads share one pool of `concurrency` permits,
+* downloads share one client, time out and retry with exponential backoff on transient errors
 */
 
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::{Path};
-use std::error::Error;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 // crates
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use tokio::sync::Semaphore;
 use url::Url;
+// local
+use crate::config::FetchConfig;
+use crate::error::PackError;
+
+// first retry waits this long, then twice as long each time
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+const LOCKFILE_HEADER: &str = "# generated by htmlpacker, pins every remote asset to its sha256\n";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    // url -> what it resolved to, sorted so diffs stay small
+    remote: BTreeMap<String, LockEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockEntry {
+    sha256: String,
+    size: usize,
+}
+
+#[derive(Debug, Default)]
+struct LockState {
+    lockfile: Lockfile,
+    // urls asked for during this pack, the rest gets pruned on save
+    used: HashSet<String>,
+    changed: bool,
+}
+
+// one per pack, shared by everything that needs a remote file
+#[derive(Debug)]
+pub struct Fetcher {
+    offline: bool,
+    frozen: bool,
+    concurrency: usize,
+    retries: u32,
+    integrity: BTreeMap<String, String>,
+    cache_dir: Option<PathBuf>,
+    lock_path: Option<PathBuf>,
+    lock: Mutex<LockState>,
+    client: reqwest::Client,
+    permits: Semaphore,
+    // for the progress lines
+    started: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+impl Fetcher {
+    pub fn new(config: &FetchConfig) -> Result<Fetcher, PackError> {
+        let cache_dir = config.cache_dir.clone().or_else(default_cache_dir);
+        let lockfile = match &config.lockfile {
+            Some(path) => read_lockfile(path)?,
+            None => Lockfile::default(),
+        };
+        if config.offline && cache_dir.is_none() {
+            return Err(PackError::Unsupported {
+                key: Some("fetch.cache_dir".to_string()),
+                message: "offline builds need a cache directory".to_string(),
+            });
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()
+            .map_err(|e| PackError::Unsupported {
+                key: Some("fetch".to_string()),
+                message: format!("cannot create http client: {}", e),
+            })?;
+        let concurrency = config.concurrency.max(1);
+        Ok(Fetcher {
+            offline: config.offline,
+            frozen: config.frozen,
+            concurrency,
+            retries: config.retries,
+            client,
+            permits: Semaphore::new(concurrency),
+            started: AtomicUsize::new(0),
+            finished: AtomicUsize::new(0),
+            integrity: config.integrity.clone(),
+            cache_dir,
+            lock_path: config.lockfile.clone(),
+            lock: Mutex::new(LockState {
+                lockfile,
+                ..Default::default()
+            }),
+        })
+    }
+
+    // how many reads and fetches a list of sources may run at once
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
 
+    // local reads share the permits with downloads
+    pub async fn get_local_text(&self, path: &Path) -> Result<String, PackError> {
+        let bytes = self.get_local_bytes(path).await?;
+        String::from_utf8(bytes).map_err(|e| PackError::Encoding {
+            id: path.display().to_string(),
+            key: None,
+            message: e.to_string(),
+        })
+    }
 
-// go through each external script file
-// saving 
-pub async fn get_external_scripts_text(
-    script_urls: Vec<&str>
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut script_strings: Vec<String> = vec![];
-    for url in script_urls {
-        script_strings.push(reqwest::get(url).await?.text().await?);
+    pub async fn get_local_bytes(&self, path: &Path) -> Result<Vec<u8>, PackError> {
+        let _permit = self.permits.acquire().await
+            .expect("the fetch semaphore is never closed");
+        tokio::fs::read(path).await.map_err(|source| PackError::AssetRead {
+            path: path.to_path_buf(),
+            key: None,
+            source,
+        })
     }
-    Ok(script_strings) 
+
+    pub async fn get_remote_text(&self, url: &Url) -> Result<String, PackError> {
+        let bytes = self.get_remote_bytes(url).await?;
+        String::from_utf8(bytes).map_err(|e| PackError::Encoding {
+            id: url.to_string(),
+            key: None,
+            message: e.to_string(),
+        })
+    }
+
+    // whatever way the bytes came in, a pinned integrity must match
+    pub async fn get_remote_bytes(&self, url: &Url) -> Result<Vec<u8>, PackError> {
+        let bytes = self.resolve_remote_bytes(url).await?;
+        if let Some(expected) = self.integrity.get(url.as_str()) {
+            verify_integrity(url, expected, &bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    // locked hash first, then the cache, then the network
+    async fn resolve_remote_bytes(&self, url: &Url) -> Result<Vec<u8>, PackError> {
+        let locked = {
+            let mut state = self.lock.lock().unwrap();
+            state.used.insert(url.to_string());
+            state.lockfile.remote.get(url.as_str()).cloned()
+        };
+
+        let hash = match &locked {
+            Some(entry) => Some(entry.sha256.clone()),
+            // without a lock entry only offline builds trust the url index
+            None if self.offline => self.read_url_index(url),
+            None => None,
+        };
+        if let Some(bytes) = hash.as_deref().and_then(|hash| self.read_blob(hash)) {
+            self.record(url, &bytes);
+            return Ok(bytes);
+        }
+
+        if self.offline {
+            return Err(PackError::RemoteFetch {
+                url: url.to_string(),
+                status: None,
+                key: None,
+                message: match locked {
+                    Some(entry) => format!(
+                        "locked to sha256 {} but not in the cache, \
+                        build once with network access", entry.sha256,
+                    ),
+                    None => "not in the cache or lockfile, \
+                        build once with network access".to_string(),
+                },
+            });
+        }
+
+        // an integrity pins the content just as well as the lockfile
+        if self.frozen && locked.is_none() && !self.integrity.contains_key(url.as_str()) {
+            return Err(PackError::RemoteFetch {
+                url: url.to_string(),
+                status: None,
+                key: None,
+                message: "not pinned by the lockfile or an integrity, \
+                    reproducible builds only use pinned assets, \
+                    build once without --reproducible to lock it".to_string(),
+            });
+        }
+
+        let bytes = self.download(url).await?;
+        let actual = sha256_hex(&bytes);
+        if let Some(entry) = locked {
+            if entry.sha256 != actual {
+                return Err(PackError::RemoteFetch {
+                    url: url.to_string(),
+                    status: None,
+                    key: None,
+                    message: format!(
+                        "content changed since it was locked\n  \
+                        expected sha256 {}\n  \
+                        actual   sha256 {}\n\
+                        remove its entry from the lockfile to accept the new version",
+                        entry.sha256, actual,
+                    ),
+                });
+            }
+        }
+        self.write_cache(url, &actual, &bytes);
+        self.record(url, &bytes);
+        Ok(bytes)
+    }
+
+    // one download, retried on timeouts, connection errors and 5xx/429
+    async fn download(&self, url: &Url) -> Result<Vec<u8>, PackError> {
+        let _permit = self.permits.acquire().await
+            .expect("the fetch semaphore is never closed");
+        self.started.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        let mut attempt = 0;
+        loop {
+            match self.try_download(url).await {
+                Ok(bytes) => {
+                    let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+                    println!(
+                        "⬇️  [{}/{}] {} ({:.1} KB in {} ms)",
+                        finished,
+                        self.started.load(Ordering::Relaxed),
+                        url,
+                        bytes.len() as f64 / 1024.0,
+                        start.elapsed().as_millis(),
+                    );
+                    return Ok(bytes);
+                }
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    // the backoff stops growing at 64x, or a large retry count would overflow it
+                    let delay = RETRY_BASE_DELAY * 2u32.saturating_pow(attempt.min(6));
+                    attempt += 1;
+                    eprintln!(
+                        "⚠️  {} failed ({}), retry {}/{} in {} ms",
+                        url, e, attempt, self.retries, delay.as_millis(),
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    self.finished.fetch_add(1, Ordering::Relaxed);
+                    return Err(fetch_error(url, e));
+                }
+            }
+        }
+    }
+
+    async fn try_download(&self, url: &Url) -> Result<Vec<u8>, reqwest::Error> {
+        let response = self.client.get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    // write the lockfile back if anything was added or went unused
+    pub fn save_lock(&self) -> Result<(), PackError> {
+        let Some(path) = self.lock_path.as_ref().filter(|_| !self.frozen) else {
+            return Ok(());
+        };
+        let mut state = self.lock.lock().unwrap();
+        let used = std::mem::take(&mut state.used);
+        let before = state.lockfile.remote.len();
+        state.lockfile.remote.retain(|url, _| used.contains(url));
+        if !state.changed && state.lockfile.remote.len() == before {
+            return Ok(());
+        }
+        let output_error = |source| PackError::Output {
+            target: path.display().to_string(),
+            source,
+        };
+        let yaml = serde_yaml::to_string(&state.lockfile)
+            .map_err(|e| output_error(std::io::Error::other(e)))?;
+        fs::write(path, format!("{}{}", LOCKFILE_HEADER, yaml))
+            .map_err(output_error)?;
+        println!("Updated lockfile {}", path.display());
+        state.changed = false;
+        Ok(())
+    }
+
+    fn record(&self, url: &Url, bytes: &[u8]) {
+        let entry = LockEntry {
+            sha256: sha256_hex(bytes),
+            size: bytes.len(),
+        };
+        let mut state = self.lock.lock().unwrap();
+        let previous = state.lockfile.remote.insert(url.to_string(), entry.clone());
+        if previous.as_ref() != Some(&entry) {
+            state.changed = true;
+        }
+    }
+
+    // blobs are checked against their name, a corrupt one is a miss
+    fn read_blob(&self, hash: &str) -> Option<Vec<u8>> {
+        let path = self.cache_dir.as_ref()?.join("blobs").join(hash);
+        let bytes = fs::read(path).ok()?;
+        (sha256_hex(&bytes) == hash).then_some(bytes)
+    }
+
+    fn read_url_index(&self, url: &Url) -> Option<String> {
+        let path = self.cache_dir.as_ref()?
+            .join("urls")
+            .join(sha256_hex(url.as_str().as_bytes()));
+        fs::read_to_string(path).ok().map(|hash| hash.trim().to_string())
+    }
+
+    // the cache is best effort, a failed write only costs a refetch
+    fn write_cache(&self, url: &Url, hash: &str, bytes: &[u8]) {
+        let Some(cache_dir) = &self.cache_dir else {
+            return;
+        };
+        let written = fs::create_dir_all(cache_dir.join("blobs"))
+            .and_then(|_| fs::create_dir_all(cache_dir.join("urls")))
+            .and_then(|_| fs::write(cache_dir.join("blobs").join(hash), bytes))
+            .and_then(|_| fs::write(
+                cache_dir.join("urls").join(sha256_hex(url.as_str().as_bytes())),
+                hash,
+            ));
+        if let Err(e) = written {
+            eprintln!("⚠️  cannot write {} to the cache {}: {}", url, cache_dir.display(), e);
+        }
+    }
+}
+
+fn read_lockfile(path: &Path) -> Result<Lockfile, PackError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        // first build, the lockfile gets created at the end
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Lockfile::default()),
+        Err(source) => return Err(PackError::ConfigRead {
+            path: path.to_path_buf(),
+            source,
+        }),
+    };
+    serde_yaml::from_str::<Option<Lockfile>>(&text)
+        .map(Option::unwrap_or_default)
+        .map_err(|e| PackError::ConfigParse {
+            path: Some(path.to_path_buf()),
+            key: None,
+            message: e.to_string(),
+        })
+}
+
+// subresource integrity, the same format browsers use
+// "sha256-<base64>" or "sha384-<base64>", several may be given space separated
+pub fn integrity(bytes: &[u8], algorithm: &str) -> Option<String> {
+    let digest = match algorithm {
+        "sha256" => Sha256::digest(bytes).to_vec(),
+        "sha384" => Sha384::digest(bytes).to_vec(),
+        _ => return None,
+    };
+    Some(format!("{}-{}", algorithm, BASE64_STANDARD.encode(digest)))
 }
 
-// go through each local script file
-pub fn get_local_scripts_text(
-    script_path_literals: Vec<&str>
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut script_strings: Vec<String> = vec![];
-    for path in script_path_literals {
-        script_strings.push(
-            get_local_script(Path::new(path)).unwrap()
-        )
-    }
-    Ok(script_strings)
+// checks the format only, returns why it is invalid
+pub fn check_integrity(value: &str) -> Result<(), String> {
+    let mut hashes = value.split_ascii_whitespace().peekable();
+    if hashes.peek().is_none() {
+        return Err("integrity is empty".to_string());
+    }
+    for hash in hashes {
+        let (algorithm, digest) = hash.split_once('-')
+            .ok_or_else(|| format!("'{}' is not <algorithm>-<base64>", hash))?;
+        if algorithm != "sha256" && algorithm != "sha384" {
+            return Err(format!("unsupported algorithm '{}', use sha256 or sha384", algorithm));
+        }
+        BASE64_STANDARD.decode(digest)
+            .map_err(|e| format!("'{}' is not valid base64: {}", digest, e))?;
+    }
+    Ok(())
+}
+
+// any of the given hashes matching is enough, like in the browser
+fn verify_integrity(url: &Url, expected: &str, bytes: &[u8]) -> Result<(), PackError> {
+    let hashes: Vec<&str> = expected.split_ascii_whitespace().collect();
+    let matches = hashes.iter().any(|hash| {
+        let algorithm = hash.split('-').next().unwrap_or_default();
+        integrity(bytes, algorithm).as_deref() == Some(*hash)
+    });
+    if matches {
+        return Ok(());
+    }
+    let actual = hashes.iter()
+        .filter_map(|hash| integrity(bytes, hash.split('-').next().unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Err(PackError::Integrity {
+        url: url.to_string(),
+        key: None,
+        expected: expected.to_string(),
+        actual,
+    })
 }
 
-pub fn get_local_script(path: &Path) -> Result<String, Box<dyn Error>> {
-    let mut file = File::open(path)?;
-    let mut text = String::new();
-    file.read_to_string(&mut text)?;
-    Ok(text)
+// worth another try: the server or the network may do better next time
+fn is_transient(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => status.is_server_error() || status.as_u16() == 429,
+        None => error.is_timeout() || error.is_connect(),
+    }
 }
 
-pub fn get_local_file(path: &Path) -> Result<String, Box<dyn Error>> {
-    let mut file = File::open(path)?;
-    let mut text = String::new();
-    file.read_to_string(&mut text)?;
-    Ok(text)
+fn default_cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("htmlpacker"))
 }
 
-pub async fn get_remote_file(url: Url) -> Result<String, Box<dyn Error>> {
-    let text = reqwest::get(url).await?.text().await?;
-    Ok(text)
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
 }
 
-// append each external css file together
-pub async fn get_css_string(
-    css_urls: Vec<&str>
-) -> Result<String, Box<dyn Error>> {
-    let mut css_string = String::from(""); 
-    for url in css_urls {
-        css_string += &reqwest::get(url).await?.text().await?;
+// keep the http status around when the server did answer
+fn fetch_error(url: &Url, error: reqwest::Error) -> PackError {
+    PackError::RemoteFetch {
+        url: url.to_string(),
+        status: error.status().map(|s| s.as_u16()),
+        key: None,
+        message: error.to_string(),
     }
-    Ok(css_string)
 }
@@ -0,0 +1,116 @@
+/*
+* fonts.rs
+*
+* embed fonts as @font-face rules with a base64 data uri
+* optionally cut the font down to the glyphs that are actually needed,
+* a given set of characters and/or the text used in the packed html
+*/
+
+use std::collections::BTreeSet;
+
+use allsorts::binary::read::ReadScope;
+use allsorts::font::MatchingPresentation;
+use allsorts::font_data::FontData;
+use allsorts::subset::{subset, CmapTarget, SubsetProfile};
+use allsorts::Font;
+
+use crate::config::FontConfig;
+use crate::encoder;
+use crate::error::PackError;
+
+// one @font-face rule with the font inlined
+pub fn font_face(
+    font: &FontConfig,
+    buffer: &[u8],
+    name: Option<&str>,
+) -> String {
+    let mime = encoder::sniff_mime(buffer, name);
+    let format = match mime {
+        "font/woff2" => "woff2",
+        "font/woff" => "woff",
+        "font/otf" => "opentype",
+        _ => "truetype",
+    };
+    let mut rule = String::from("@font-face {\n");
+    rule.push_str(&format!("    font-family: {};\n", quote_css(&font.family)));
+    rule.push_str(&format!(
+        "    src: url(\"{}\") format(\"{}\");\n",
+        encoder::data_uri(buffer, mime),
+        format,
+    ));
+    rule.push_str(&format!(
+        "    font-weight: {};\n",
+        font.weight.as_deref().unwrap_or("normal"),
+    ));
+    rule.push_str(&format!(
+        "    font-style: {};\n",
+        font.style.as_deref().unwrap_or("normal"),
+    ));
+    if let Some(display) = &font.display {
+        rule.push_str(&format!("    font-display: {};\n", display));
+    }
+    rule.push_str("}\n");
+    rule
+}
+
+// keep only the glyphs for the given characters
+// the result is a plain truetype/opentype font, even from woff input
+pub fn subset_font(
+    buffer: &[u8],
+    chars: &BTreeSet<char>,
+    id: &str,
+) -> Result<Vec<u8>, PackError> {
+    let font_error = |message: String| PackError::Encoding {
+        id: id.to_string(),
+        key: None,
+        message,
+    };
+    let font_data = ReadScope::new(buffer)
+        .read::<FontData<'_>>()
+        .map_err(|e| font_error(format!("cannot read font: {}", e)))?;
+
+    // characters to glyph ids through the font's cmap
+    let provider = font_data.table_provider(0)
+        .map_err(|e| font_error(format!("cannot read font tables: {}", e)))?;
+    let mut font = Font::new(provider)
+        .map_err(|e| font_error(format!("cannot read font: {}", e)))?;
+    let mut glyphs = BTreeSet::new();
+    for &c in chars {
+        let (glyph, _) = font.lookup_glyph_index(c, MatchingPresentation::NotRequired, None);
+        if glyph != 0 {
+            glyphs.insert(glyph);
+        }
+    }
+    // .notdef always comes first
+    let mut glyph_ids = vec![0];
+    glyph_ids.extend(glyphs);
+
+    let provider = font_data.table_provider(0)
+        .map_err(|e| font_error(format!("cannot read font tables: {}", e)))?;
+    subset(&provider, &glyph_ids, &SubsetProfile::Minimal, CmapTarget::Unicode)
+        .map_err(|e| font_error(format!("cannot subset font: {}", e)))
+}
+
+// every character that shows up as text in the html fragments
+// tags are skipped, entities are left as is (a few extra letters)
+pub fn used_chars(html_texts: &[String]) -> BTreeSet<char> {
+    let mut chars = BTreeSet::new();
+    for text in html_texts {
+        let mut in_tag = false;
+        for c in text.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                c if !in_tag && !c.is_control() => {
+                    chars.insert(c);
+                }
+                _ => (),
+            }
+        }
+    }
+    chars
+}
+
+fn quote_css(family: &str) -> String {
+    format!("\"{}\"", family.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,533 @@
+/*
+* fragment.rs
+*
+* make a user html fragment (or a whole entry document) self contained
+* images and media become data uris
+* linked stylesheets are pulled out into the style block,
+* or replaced by a <style> in place for entry documents
+* <style> blocks get their url() and @import inlined too
+* external scripts become inline script blocks, in place,
+* deferred ones move to the end of the body where they still run last
+* references are resolved against the fragment's own path or url
+*/
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use lol_html::html_content::{ContentType, Element};
+use lol_html::{element, end, rewrite_str, text, EndTagHandler, RewriteStrSettings};
+
+use crate::css::{self, Origin};
+use crate::error::PackError;
+use crate::fetcher::Fetcher;
+use crate::html;
+
+// never closed, or closed by leaving their parent, both are fine
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input",
+    "link", "meta", "param", "source", "track", "wbr",
+];
+const OPTIONAL_END: [&str; 16] = [
+    "p", "li", "dt", "dd", "option", "optgroup", "tr", "td", "th",
+    "thead", "tbody", "tfoot", "colgroup", "caption", "rp", "rt",
+];
+// whose contents are text up to their own closing tag
+const RAW_TEXT: [&str; 4] = ["script", "style", "textarea", "title"];
+
+// elements whose src/srcset/poster point at images or media
+const MEDIA_ELEMENTS: &str = "img, source, video, audio, track, input[type=image]";
+const MEDIA_ATTRIBUTES: [&str; 2] = ["src", "poster"];
+// link rels that point at an image
+const ICON_RELS: [&str; 4] = ["icon", "shortcut", "apple-touch-icon", "mask-icon"];
+
+// what happens to <link rel="stylesheet">
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stylesheets {
+    // removed and handed back, they go into the page's style block
+    Extract,
+    // replaced by a <style> right where the link was
+    InPlace,
+}
+
+pub struct Fragment {
+    pub html: String,
+    // extracted stylesheets, already inlined, in document order
+    pub styles: Vec<String>,
+    // the html brings its own favicon
+    pub has_icon: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Media,
+    Style,
+    Script,
+}
+
+// parse the fragment, fetch what it references, write it back inlined
+// anything that cannot be fetched is left as is, with a warning
+pub async fn inline_fragment(
+    html: &str,
+    origin: &Origin,
+    key: &str,
+    stylesheets: Stylesheets,
+    fetcher: &Fetcher,
+) -> Result<Fragment, PackError> {
+    // first pass only collects, lol_html handlers cannot await
+    let found: RefCell<Vec<(Kind, String)>> = RefCell::new(vec![]);
+    let style_blocks: RefCell<Vec<String>> = RefCell::new(vec![]);
+    let style_block = RefCell::new(String::new());
+    let has_icon = Cell::new(false);
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(MEDIA_ELEMENTS, |el| {
+                for reference in media_references(el) {
+                    found.borrow_mut().push((Kind::Media, reference));
+                }
+                Ok(())
+            }),
+            element!("link[href]", |el| {
+                let kind = link_kind(el);
+                if kind == Some(Kind::Media) {
+                    has_icon.set(true);
+                }
+                if let (Some(kind), Some(href)) = (kind, el.get_attribute("href")) {
+                    found.borrow_mut().push((kind, href));
+                }
+                Ok(())
+            }),
+            element!("script[src]", |el| {
+                if let Some(src) = el.get_attribute("src") {
+                    found.borrow_mut().push((Kind::Script, src));
+                }
+                Ok(())
+            }),
+            // style text can come in several chunks
+            text!("style", |chunk| {
+                style_block.borrow_mut().push_str(chunk.as_str());
+                if chunk.last_in_text_node() {
+                    style_blocks.borrow_mut().push(style_block.take());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| fragment_error(key, e))?;
+
+    let inlined_blocks: Vec<String> = stream::iter(style_blocks.into_inner())
+        .map(|block| async move { css::inline_css(&block, origin, fetcher).await })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await?;
+
+    // every distinct reference is fetched once, all at the same time
+    let mut references = found.into_inner();
+    let mut seen = HashSet::new();
+    references.retain(|reference| seen.insert(reference.clone()));
+    let resolved: HashMap<(Kind, String), String> = stream::iter(references)
+        .filter_map(|(kind, reference)| async move {
+            origin.resolve(&reference).map(|target| (kind, reference, target))
+        })
+        .map(|(kind, reference, target)| async move {
+            match fetch(kind, &target, fetcher).await {
+                Ok(content) => Some(((kind, reference), content)),
+                Err(e) => {
+                    eprintln!("⚠️  cannot inline {} in {}: {}", reference, origin, e);
+                    None
+                }
+            }
+        })
+        .buffered(fetcher.concurrency())
+        .filter_map(|resolved| async move { resolved })
+        .collect()
+        .await;
+
+    // second pass swaps every resolved reference for its content
+    let styles: RefCell<Vec<String>> = RefCell::new(vec![]);
+    // end tag handlers outlive the pass, so the deferred scripts are shared
+    let deferred: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(vec![]));
+    let mut inlined_blocks = inlined_blocks.into_iter();
+    let lookup = |kind: Kind, reference: &str| {
+        resolved.get(&(kind, reference.to_string())).cloned()
+    };
+    let html = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(MEDIA_ELEMENTS, |el| {
+                for attribute in MEDIA_ATTRIBUTES {
+                    let data_uri = el.get_attribute(attribute)
+                        .and_then(|value| lookup(Kind::Media, value.trim()));
+                    if let Some(data_uri) = data_uri {
+                        el.set_attribute(attribute, &data_uri)?;
+                    }
+                }
+                if let Some(srcset) = srcset(el) {
+                    let rewritten = srcset_candidates(&srcset)
+                        .into_iter()
+                        .map(|(url, descriptor)| {
+                            let url = lookup(Kind::Media, url).unwrap_or(url.to_string());
+                            format!("{} {}", url, descriptor).trim_end().to_string()
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    el.set_attribute("srcset", &rewritten)?;
+                }
+                Ok(())
+            }),
+            element!("link[href]", |el| {
+                let (Some(kind), Some(href)) = (link_kind(el), el.get_attribute("href")) else {
+                    return Ok(());
+                };
+                match (kind, lookup(kind, &href)) {
+                    (Kind::Style, Some(css)) => match stylesheets {
+                        // the style block has no media of its own, the css carries it
+                        Stylesheets::Extract => {
+                            let css = match el.get_attribute("media") {
+                                Some(media) if !media.trim().is_empty() && media.trim() != "all" => {
+                                    format!("@media {} {{\n{}\n}}", media.trim(), css)
+                                }
+                                _ => css,
+                            };
+                            styles.borrow_mut().push(css);
+                            el.remove();
+                        }
+                        Stylesheets::InPlace => {
+                            let media = el.get_attribute("media")
+                                .map(|media| format!(" media=\"{}\"", media))
+                                .unwrap_or_default();
+                            el.replace(
+                                &format!("<style{}>\n{}\n</style>", media, html::escape_style(&css)),
+                                ContentType::Html,
+                            );
+                        }
+                    },
+                    (_, Some(data_uri)) => el.set_attribute("href", &data_uri)?,
+                    _ => (),
+                }
+                Ok(())
+            }),
+            element!("script[src]", |el| {
+                let js = el.get_attribute("src")
+                    .and_then(|src| lookup(Kind::Script, &src));
+                let Some(js) = js else {
+                    return Ok(());
+                };
+                // async and defer do nothing for inline scripts
+                // module scripts are deferred anyway
+                let module = el.get_attribute("type").is_some_and(|t| t.trim() == "module");
+                let defer = el.has_attribute("defer") && !el.has_attribute("async") && !module;
+                el.remove_attribute("src");
+                el.remove_attribute("defer");
+                if !module {
+                    el.remove_attribute("async");
+                }
+                let js = format!("\n{}\n", html::escape_script(&js));
+                if defer {
+                    deferred.borrow_mut().push(format!("<script{}>{}</script>", attributes(el), js));
+                    el.remove();
+                } else {
+                    el.set_inner_content(&js, ContentType::Html);
+                }
+                Ok(())
+            }),
+            element!("body", |el| {
+                let deferred = Rc::clone(&deferred);
+                if let Some(handlers) = el.end_tag_handlers() {
+                    let handler: EndTagHandler = Box::new(move |end| {
+                        end.before(&deferred.take().concat(), ContentType::Html);
+                        Ok(())
+                    });
+                    handlers.push(handler);
+                }
+                Ok(())
+            }),
+            // the whole block goes out with its last chunk
+            text!("style", |chunk| {
+                if chunk.last_in_text_node() {
+                    let css = inlined_blocks.next().unwrap_or_default();
+                    chunk.replace(&html::escape_style(&css), ContentType::Html);
+                } else {
+                    chunk.remove();
+                }
+                Ok(())
+            }),
+        ],
+        // a fragment has no body, its deferred scripts go last
+        document_content_handlers: vec![
+            end!(|end| {
+                end.append(&deferred.take().concat(), ContentType::Html);
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| fragment_error(key, e))?;
+
+    Ok(Fragment {
+        html,
+        styles: styles.into_inner(),
+        has_icon: has_icon.get(),
+    })
+}
+
+// a data uri for media, the text itself for stylesheets and scripts
+async fn fetch(
+    kind: Kind,
+    target: &Origin,
+    fetcher: &Fetcher,
+) -> Result<String, PackError> {
+    match kind {
+        Kind::Media => target.fetch_data_uri(fetcher).await,
+        Kind::Style => {
+            let bytes = target.fetch(fetcher).await?;
+            css::inline_css(&String::from_utf8_lossy(&bytes), target, fetcher).await
+        }
+        Kind::Script => {
+            let bytes = target.fetch(fetcher).await?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+fn media_references(el: &Element) -> Vec<String> {
+    let mut references: Vec<String> = MEDIA_ATTRIBUTES.iter()
+        .filter_map(|attribute| el.get_attribute(attribute))
+        .map(|value| value.trim().to_string())
+        .collect();
+    if let Some(srcset) = srcset(el) {
+        references.extend(
+            srcset_candidates(&srcset).into_iter().map(|(url, _)| url.to_string())
+        );
+    }
+    references
+}
+
+// a srcset that already holds data uris cannot be split on commas
+// the attributes as written, for an element that moves
+fn attributes(el: &Element) -> String {
+    el.attributes()
+        .iter()
+        .map(|attribute| {
+            let value = attribute.value().replace('&', "&amp;").replace('"', "&quot;");
+            format!(" {}=\"{}\"", attribute.name(), value)
+        })
+        .collect()
+}
+
+fn srcset(el: &Element) -> Option<String> {
+    el.get_attribute("srcset").filter(|srcset| !srcset.contains("data:"))
+}
+
+// stylesheets get inlined as css, icons as data uris, the rest is left alone
+fn link_kind(el: &Element) -> Option<Kind> {
+    let rel = el.get_attribute("rel")?.to_ascii_lowercase();
+    let rels: Vec<&str> = rel.split_ascii_whitespace().collect();
+    if rels.contains(&"stylesheet") {
+        Some(Kind::Style)
+    } else if rels.iter().any(|r| ICON_RELS.contains(r)) {
+        Some(Kind::Media)
+    } else {
+        None
+    }
+}
+
+// "a.png 1x, b.png 2x" -> [("a.png", "1x"), ("b.png", "2x")]
+fn srcset_candidates(srcset: &str) -> Vec<(&str, &str)> {
+    srcset.split(',')
+        .map(str::trim)
+        .filter(|candidate| !candidate.is_empty())
+        .map(|candidate| match candidate.split_once(char::is_whitespace) {
+            Some((url, descriptor)) => (url, descriptor.trim()),
+            None => (candidate, ""),
+        })
+        .collect()
+}
+
+// a fragment is pasted into the page as is, an unclosed <div> swallows
+// everything after it and a stray </div> closes something of ours
+// one line per problem, nothing is changed
+pub fn unbalanced(html: &str) -> Vec<String> {
+    let line = |at: usize| html[..at].matches('\n').count() + 1;
+    let mut problems: Vec<String> = vec![];
+    // (name, line)
+    let mut open: Vec<(String, usize)> = vec![];
+    let mut at = 0;
+    while let Some(found) = html[at..].find('<') {
+        let start = at + found;
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            match rest.find("-->") {
+                Some(end) => at = start + end + 3,
+                None => {
+                    problems.push(format!("comment on line {} is never closed", line(start)));
+                    return problems;
+                }
+            }
+            continue;
+        }
+        let closing = rest.starts_with("</");
+        let name: String = rest[if closing { 2 } else { 1 }..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == ':')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        // <!doctype>, <?xml> or a lone <
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            at = start + 1;
+            continue;
+        }
+        let end = tag_end(html, start).unwrap_or(html.len());
+        at = end;
+
+        if closing {
+            if VOID_ELEMENTS.contains(&name.as_str()) {
+                continue;
+            }
+            match open.iter().rposition(|(open_name, _)| *open_name == name) {
+                Some(index) => {
+                    for (inner, inner_line) in open.drain(index..).skip(1) {
+                        if !OPTIONAL_END.contains(&inner.as_str()) {
+                            problems.push(format!(
+                                "<{}> on line {} is closed by </{}> on line {}",
+                                inner, inner_line, name, line(start),
+                            ));
+                        }
+                    }
+                }
+                None if OPTIONAL_END.contains(&name.as_str()) => (),
+                None => problems.push(format!(
+                    "</{}> on line {} closes nothing", name, line(start)
+                )),
+            }
+            continue;
+        }
+
+        let self_closing = html[..end].ends_with("/>");
+        if VOID_ELEMENTS.contains(&name.as_str()) || self_closing {
+            continue;
+        }
+        if RAW_TEXT.contains(&name.as_str()) {
+            // skip the text, the closing tag is handled on the next round
+            let close = format!("</{}", name);
+            match html[end..].to_ascii_lowercase().find(&close) {
+                Some(offset) => at = end + offset,
+                None => {
+                    problems.push(format!("<{}> on line {} is never closed", name, line(start)));
+                    return problems;
+                }
+            }
+        }
+        open.push((name, line(start)));
+    }
+    for (name, open_line) in open {
+        if !OPTIONAL_END.contains(&name.as_str()) {
+            problems.push(format!("<{}> on line {} is never closed", name, open_line));
+        }
+    }
+    problems
+}
+
+// the index after the > of the tag starting at start, quotes respected
+fn tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (offset, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(start + offset + 1),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn fragment_error(key: &str, error: impl std::fmt::Display) -> PackError {
+    PackError::Encoding {
+        id: key.to_string(),
+        key: None,
+        message: format!("cannot rewrite html: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::FetchConfig;
+
+    // a fragment next to the files it links, inlined from a temp dir
+    async fn inline(test: &str, html: &str, stylesheets: Stylesheets) -> Fragment {
+        let dir = std::env::temp_dir()
+            .join(format!("htmlpacker-fragment-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.js"), "document.body.dataset.ready = 1;").unwrap();
+        fs::write(dir.join("sync.js"), "var sync = 1;").unwrap();
+        fs::write(dir.join("print.css"), "p { color: black; }").unwrap();
+        let origin = Origin::Local(dir.join("index.html"));
+        let fetcher = Fetcher::new(&FetchConfig::default()).unwrap();
+        let fragment = inline_fragment(html, &origin, "html.local[0]", stylesheets, &fetcher).await;
+        fs::remove_dir_all(&dir).unwrap();
+        fragment.unwrap()
+    }
+
+    fn position(html: &str, part: &str) -> usize {
+        html.find(part).unwrap_or_else(|| panic!("{} is missing from {}", part, html))
+    }
+
+    #[tokio::test]
+    async fn deferred_scripts_move_to_the_end_of_the_body() {
+        let html = "<html><head>\
+            <script defer src=\"app.js\" data-name=\"app\"></script>\
+            <script async src=\"sync.js\"></script>\
+            </head><body><p id=\"content\">hi</p></body></html>";
+        let fragment = inline("defer", html, Stylesheets::InPlace).await;
+        let html = fragment.html;
+
+        let deferred = position(&html, "dataset.ready");
+        assert!(position(&html, "var sync") < position(&html, "<body>"));
+        assert!(position(&html, "<p id=\"content\">") < deferred);
+        assert!(deferred < position(&html, "</body>"));
+        assert!(html.contains("<script data-name=\"app\">"), "{}", html);
+        assert!(!html.contains("defer") && !html.contains("async"), "{}", html);
+    }
+
+    #[tokio::test]
+    async fn deferred_scripts_go_last_in_a_fragment() {
+        let html = "<script defer src=\"app.js\"></script><p>hi</p>";
+        let fragment = inline("defer-fragment", html, Stylesheets::Extract).await;
+        assert!(fragment.html.starts_with("<p>hi</p><script>"), "{}", fragment.html);
+    }
+
+    #[tokio::test]
+    async fn extracted_stylesheets_keep_their_media() {
+        let html = "<link rel=\"stylesheet\" media=\"print\" href=\"print.css\">\
+            <link rel=\"stylesheet\" media=\"all\" href=\"print.css\"><p>hi</p>";
+        let fragment = inline("media", html, Stylesheets::Extract).await;
+        assert_eq!(fragment.styles, vec![
+            "@media print {\np { color: black; }\n}".to_string(),
+            "p { color: black; }".to_string(),
+        ]);
+        assert_eq!(fragment.html, "<p>hi</p>");
+    }
+
+    #[test]
+    fn unbalanced_warns_about_unclosed_fragments() {
+        assert_eq!(unbalanced("<section>\n<div>\n<span>hi</div>"), vec![
+            "<span> on line 3 is closed by </div> on line 3".to_string(),
+            "<section> on line 1 is never closed".to_string(),
+        ]);
+        assert_eq!(unbalanced("<div>\n</span></div>"), vec![
+            "</span> on line 2 closes nothing".to_string(),
+        ]);
+        assert_eq!(unbalanced("<!-- open"), vec!["comment on line 1 is never closed".to_string()]);
+    }
+
+    #[test]
+    fn balanced_fragments_pass() {
+        let html = "<!DOCTYPE html><ul><li>one<li>two</ul><img src=\"a.png\"><br/>\
+            <script>if (a < b) { s = '<div>'; }</script><p>text</p><!-- <div> -->";
+        assert!(unbalanced(html).is_empty(), "{:?}", unbalanced(html));
+    }
+}
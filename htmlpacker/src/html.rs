@@ -5,15 +5,21 @@
 * pack the sources into their part of the html
 */
 
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::fs::File;
 use std::fs;
 use std::io::prelude::*;
 use std::path::{PathBuf};
-use std::error::Error;
 // crates
+use lol_html::html_content::ContentType;
+use lol_html::{element, end, rewrite_str, RewriteStrSettings};
 use maud::{DOCTYPE, html, Markup, PreEscaped};
 // local
+use crate::config::MetaConfig;
 use crate::encoder::Base;
+use crate::error::PackError;
+use crate::favicon::Icon;
 
 //use htmlpacker::encoder;
 
@@ -30,10 +36,18 @@ use crate::encoder::Base;
 //<script src="https://cdn.jsdelivr.net/npm/wasm-polyfill/wasm-polyfill.min.js"></script>
 // hmmm
 
+// the preview image already turned into a data uri by the packer
+// everything else is taken as is from the meta config
+pub struct PageMeta<'a> {
+    pub meta: Option<&'a MetaConfig>,
+    pub image: Option<String>,
+}
+
 // head part of html
 fn head(
     css: String,
-    icons: Vec<String>,
+    icons: Vec<Icon>,
+    page_meta: &PageMeta,
 ) -> Markup {
     let viewport = concat!(
         "width=device-width, ",
@@ -41,44 +55,115 @@ fn head(
         "maximum-scale=1.0, ",
         "user-scalable=1"
     );
+    let default_meta = MetaConfig::default();
+    let meta = page_meta.meta.unwrap_or(&default_meta);
+    let title = meta.title.as_deref().unwrap_or("htmlpacker");
     html! {
-        (DOCTYPE)
         "\n"
         meta charset = "utf-8";
         "\n"
         meta name = "viewport" content = (viewport);
-        //meta name = "description" content = "htmlpacked webapp"
-        //meta name = "author" content = "htmlpacker"
-        title { "htmlpacker" }
         "\n"
+        title { (title) }
+        "\n"
+        (meta_tags(meta, title, page_meta.image.as_deref()))
         (favicons(icons))
         "\n"
-        style { "\n"(css)"\n" }
+        style { "\n"(PreEscaped(escape_style(&css)))"\n" }
         "\n"
     }
 }
 
-fn favicons(icons: Vec<String>) -> Markup {
-    if icons.len() > 0 {
-        html! {
-            // basic - covers most needs
-            //link rel="icon" type="image/x-icon" href="data:image/x-icon;base64,YOUR_ICO_BASE64_HERE";
-            
-            // modern browsers - SVG favicon (best option when available)
-            //link rel="icon" type="image/svg+xml" href="data:image/svg+xml;base64,(icons[0])";
-
+// description, author, theme-color
+// then opengraph and twitter card tags for link previews
+fn meta_tags(
+    meta: &MetaConfig,
+    title: &str,
+    image: Option<&str>,
+) -> Markup {
+    let card = match (&meta.twitter_card, image) {
+        (Some(card), _) => card.as_str(),
+        (None, Some(_)) => "summary_large_image",
+        (None, None) => "summary",
+    };
+    html! {
+        @if let Some(description) = &meta.description {
+            meta name = "description" content = (description);
+            "\n"
+        }
+        @if let Some(author) = &meta.author {
+            meta name = "author" content = (author);
+            "\n"
+        }
+        @if let Some(keywords) = &meta.keywords {
+            meta name = "keywords" content = (keywords);
+            "\n"
+        }
+        @if let Some(theme_color) = &meta.theme_color {
+            meta name = "theme-color" content = (theme_color);
+            "\n"
+        }
+        // opengraph
+        meta property = "og:title" content = (title);
+        "\n"
+        meta property = "og:type" content = (meta.og_type.as_deref().unwrap_or("website"));
+        "\n"
+        @if let Some(description) = &meta.description {
+            meta property = "og:description" content = (description);
+            "\n"
+        }
+        @if let Some(url) = &meta.url {
+            meta property = "og:url" content = (url);
             "\n"
-            link rel="icon" type="image/svg+xml" href=(format!("data:image/svg+xml;base64,{}", icons[0]));
+        }
+        @if let Some(site_name) = &meta.site_name {
+            meta property = "og:site_name" content = (site_name);
+            "\n"
+        }
+        @if let Some(lang) = &meta.lang {
+            meta property = "og:locale" content = (lang.replace('-', "_"));
+            "\n"
+        }
+        @if let Some(image) = image {
+            meta property = "og:image" content = (image);
             "\n"
-            // fallback PNGs for various sizes
-            //link rel="icon" type="image/png" sizes="16x16" href="data:image/png;base64,YOUR_16x16_PNG_BASE64_HERE";
-            //link rel="icon" type="image/png" sizes="32x32" href="data:image/png;base64,YOUR_32x32_PNG_BASE64_HERE";
-            
-            // iOS/macOS support
-            //link rel="apple-touch-icon" sizes="180x180" href="data:image/png;base64,YOUR_180x180_PNG_BASE64_HERE";
+            @if let Some(alt) = &meta.image_alt {
+                meta property = "og:image:alt" content = (alt);
+                "\n"
+            }
         }
-    } else {
-        html! {
+        // twitter card
+        meta name = "twitter:card" content = (card);
+        "\n"
+        meta name = "twitter:title" content = (title);
+        "\n"
+        @if let Some(description) = &meta.description {
+            meta name = "twitter:description" content = (description);
+            "\n"
+        }
+        @if let Some(site) = &meta.twitter_site {
+            meta name = "twitter:site" content = (site);
+            "\n"
+        }
+        @if let Some(image) = image {
+            meta name = "twitter:image" content = (image);
+            "\n"
+            @if let Some(alt) = &meta.image_alt {
+                meta name = "twitter:image:alt" content = (alt);
+                "\n"
+            }
+        }
+    }
+}
+
+// every icon is a data uri with its own mime type and sizes
+// the generated set covers .ico, pngs, apple-touch-icon and mask-icon
+fn favicons(icons: Vec<Icon>) -> Markup {
+    html! {
+        "\n"
+        @for icon in &icons {
+            link rel=(icon.rel) type=(icon.mime) sizes=[icon.sizes.as_deref()]
+                href=(icon.href) color=[icon.color.as_deref()];
             "\n"
         }
     }
@@ -94,6 +179,40 @@ fn favicons(icons: Vec<String>) -> Markup {
 // unexpected semicolons from minification
 // PreEscaped does this for us.
 
+// </script and <script in a string or regex would end the script early,
+// or with an <!-- before them, keep the parser from ever ending it
+// \u003C is still < in js strings, regexes, templates and json
+// <!-- alone is harmless then, and outside a string it is a js comment
+pub fn escape_script(js: &str) -> Cow<'_, str> {
+    escape_tag_open(js, &["</script", "<script"], "\\u003C")
+}
+
+// the same for css, \3C followed by a space is < in strings and comments
+pub fn escape_style(css: &str) -> Cow<'_, str> {
+    escape_tag_open(css, &["</style"], "\\3C ")
+}
+
+// the < of every sequence, in any case, replaced by escape
+fn escape_tag_open<'a>(text: &'a str, sequences: &[&str], escape: &str) -> Cow<'a, str> {
+    let starts_sequence = |at: usize| sequences.iter().any(|sequence| {
+        text.as_bytes()[at..].get(..sequence.len())
+            .is_some_and(|bytes| bytes.eq_ignore_ascii_case(sequence.as_bytes()))
+    });
+    let mut found = text.match_indices('<').map(|(at, _)| at).filter(|at| starts_sequence(*at)).peekable();
+    if found.peek().is_none() {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 16);
+    let mut last = 0;
+    for at in found {
+        escaped.push_str(&text[last..at]);
+        escaped.push_str(escape);
+        last = at + 1;
+    }
+    escaped.push_str(&text[last..]);
+    Cow::Owned(escaped)
+}
+
 // place a bunch of html text
 fn place_html_texts(
     text: Vec<String>,
@@ -119,7 +238,7 @@ fn scripts(
         @for script in &js {
             script {
                 "\n"
-                (PreEscaped(script))
+                (PreEscaped(escape_script(script)))
                 "\n"
             }
             "\n"
@@ -140,13 +259,23 @@ fn scripts(
     }
 }
 
+// the attributes binary always writes, a <pre id> of the page itself has not
+pub const BLOB_SELECTOR: &str = "pre[id][hash][encoding]";
+
 fn binary(
     bin: Vec<Base>,
 ) -> Markup {
     html! {
         "\n"
         @for b in &bin {
-            pre id=(b.id) hash=(b.hash) style="display: none;" {
+            pre id=(b.id) hash=(b.hash)
+                encoding=(b.encoding.as_str()) compression=(b.compression.as_str())
+                mime=[b.mime.as_deref()] data-asset[b.mime.is_some()]
+                data-wasm=[b.app.as_ref().map(|app| &app.name)]
+                data-global=[b.app.as_ref().and_then(|app| app.global.as_deref())]
+                data-entry=[b.app.as_ref().and_then(|app| app.entry.as_deref())]
+                data-args=[b.app.as_ref().and_then(|app| app.args.as_deref())]
+                style="display: none;" {
                 "\n"
                 (b.text)
                 "\n"
@@ -156,22 +285,36 @@ fn binary(
     }
 }
 
+// the text of a blob as binary wrote it, without the newline on either side
+// base122 may start or end with other whitespace, so nothing else goes
+pub fn blob_text(text: &str) -> &str {
+    let text = text.strip_prefix('\n').unwrap_or(text);
+    text.strip_suffix('\n').unwrap_or(text)
+}
+
 // head
 // body
 // scripts
 // combine into page
 pub fn page(
     css: String,
-    icons: Vec<String>,
+    icons: Vec<Icon>,
     html_texts: Vec<String>,
     js: Vec<String>,
     //external_scripts_text: Vec<String>,
     //local_scripts_text: Vec<String>,
     bin: Vec<Base>,
+    meta: PageMeta,
 )
 -> Markup {
+    let lang = meta.meta.and_then(|m| m.lang.as_deref());
     html! {
-        (head(css, icons))
+        (DOCTYPE)
+        "\n"
+        html lang=[lang] {
+        head {
+            (head(css, icons, &meta))
+        }
         "\n"
         body {
             "\n"
@@ -193,26 +336,126 @@ pub fn page(
             //))
         }
         "\n"
+        }
+        "\n"
     }
 }
 
+// entry document mode
+// the user's document is the skeleton, its structure is left alone
+// our styles and icons go at the end of its head,
+// blobs, scripts and fragments at the end of its body
+pub fn entry_page(
+    document: &str,
+    css: String,
+    icons: Vec<Icon>,
+    html_texts: Vec<String>,
+    js: Vec<String>,
+    bin: Vec<Base>,
+) -> Result<String, PackError> {
+    let head_part = html! {
+        (favicons(icons))
+        @if !css.is_empty() {
+            style { "\n"(PreEscaped(escape_style(&css)))"\n" }
+            "\n"
+        }
+    }.into_string();
+    let body_part = html! {
+        (binary(bin))
+        (scripts(js))
+        (place_html_texts(html_texts))
+    }.into_string();
+
+    // documents without a head or body still get everything, at the end
+    let head_seen = Cell::new(false);
+    let body_seen = Cell::new(false);
+    rewrite_str(document, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("head", |el| {
+                head_seen.set(true);
+                el.append(&head_part, ContentType::Html);
+                Ok(())
+            }),
+            element!("body", |el| {
+                body_seen.set(true);
+                if !head_seen.get() {
+                    el.prepend(&head_part, ContentType::Html);
+                }
+                el.append(&body_part, ContentType::Html);
+                Ok(())
+            }),
+        ],
+        document_content_handlers: vec![
+            end!(|end| {
+                if !head_seen.get() && !body_seen.get() {
+                    end.append(&head_part, ContentType::Html);
+                }
+                if !body_seen.get() {
+                    end.append(&body_part, ContentType::Html);
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| PackError::Encoding {
+        id: "entry".to_string(),
+        key: None,
+        message: format!("cannot rewrite html: {}", e),
+    })
+}
+
 // save our string to an html file
 pub fn save_html(
     html: String,
     output: PathBuf,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), PackError> {
+    let output_error = |source| PackError::Output {
+        target: output.display().to_string(),
+        source,
+    };
     /*
     let output_dir = Path::new("../output");
     fs::create_dir_all(output_dir)?;
     */
     // create the directory and all its parent directories if they don't exist
     if let Some(parent) = output.parent() {
-        fs::create_dir_all(parent)?;
+        fs::create_dir_all(parent).map_err(output_error)?;
     }
     //let mut file = File::create(output_dir.join("index.html"))?;
-    let mut file = File::create(output)?;
-    file.write_all(html.as_bytes())?;
+    let mut file = File::create(&output).map_err(output_error)?;
+    file.write_all(html.as_bytes()).map_err(output_error)?;
     
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_script_breaks_script_tags() {
+        assert_eq!(escape_script("s = '</script>';"), "s = '\\u003C/script>';");
+        assert_eq!(escape_script("s = '<!--<script>';"), "s = '<!--\\u003Cscript>';");
+        assert_eq!(escape_script("/</SCRIPT\\b/i.test(x)"), "/\\u003C/SCRIPT\\b/i.test(x)");
+        assert_eq!(
+            escape_script("a('</Script><script>')"),
+            "a('\\u003C/Script>\\u003Cscript>')",
+        );
+    }
+
+    #[test]
+    fn escape_style_breaks_style_tags() {
+        assert_eq!(escape_style("a::after { content: '</style>'; }"), "a::after { content: '\\3C /style>'; }");
+        assert_eq!(escape_style("/* </STYLE */"), "/* \\3C /STYLE */");
+    }
+
+    #[test]
+    fn escaping_leaves_the_rest_alone() {
+        for text in ["if (a < b && c <= d) {}", "x = '<scrip'; y = '</scrip", "<style>", "<!-- -->", ""] {
+            assert!(matches!(escape_script(text), Cow::Borrowed(_)), "{}", text);
+        }
+        for text in ["a < b", "content: '<'", "<style>", "</script>"] {
+            assert!(matches!(escape_style(text), Cow::Borrowed(_)), "{}", text);
+        }
+    }
+}
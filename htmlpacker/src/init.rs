@@ -0,0 +1,62 @@
+/*
+* init.rs
+*
+* `htmlpacker init [dir]`
+* scaffold a commented config and a small wasm crate to start from
+* nothing is overwritten unless asked to
+*/
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::PackError;
+
+// (path inside the new project, contents)
+const TEMPLATES: [(&str, &str); 5] = [
+    ("config.yaml", include_str!("../core/init/config.yaml")),
+    ("style.css", include_str!("../core/init/style.css")),
+    ("app.html", include_str!("../core/init/app.html")),
+    ("wasm_app/Cargo.toml", include_str!("../core/init/wasm_Cargo.toml")),
+    ("wasm_app/src/lib.rs", include_str!("../core/init/wasm_lib.rs")),
+];
+
+pub fn init(dir: &Path, force: bool) -> Result<(), PackError> {
+    let files: Vec<(PathBuf, &str)> = TEMPLATES.iter()
+        .map(|(name, contents)| (dir.join(name), *contents))
+        .collect();
+
+    // check everything first, a half written project helps nobody
+    if !force {
+        if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(PackError::Output {
+                target: existing.display().to_string(),
+                source: io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "already exists, pass --force to overwrite",
+                ),
+            });
+        }
+    }
+
+    for (path, contents) in &files {
+        let output_error = |source| PackError::Output {
+            target: path.display().to_string(),
+            source,
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(output_error)?;
+        }
+        fs::write(path, contents).map_err(output_error)?;
+        println!("✅ {}", path.display());
+    }
+
+    // config paths are relative to where htmlpacker runs
+    let cd = match dir == Path::new(".") {
+        true => String::new(),
+        false => format!("cd {} && ", dir.display()),
+    };
+    println!("Build it with `{}htmlpacker build config.yaml -o index.html`", cd);
+    println!("(the wasm crate needs wasm-pack and the wasm32-unknown-unknown target)");
+    Ok(())
+}
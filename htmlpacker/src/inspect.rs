@@ -0,0 +1,172 @@
+/*
+* inspect.rs
+*
+* `htmlpacker inspect index.html`
+* what went into a packed page and where its bytes go
+* read straight from the markup, nothing is decoded
+*/
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use lol_html::{element, rewrite_str, text, RewriteStrSettings};
+
+use crate::error::PackError;
+use crate::html;
+
+// one <pre> blob as the runtime sees it
+#[derive(Debug, Default)]
+struct Blob {
+    id: String,
+    kind: String,
+    encoding: String,
+    compression: String,
+    hash: String,
+    text: String,
+    size: usize,
+}
+
+#[derive(Debug, Default)]
+struct Page {
+    title: String,
+    styles: Vec<usize>,
+    scripts: Vec<usize>,
+    // data uri length of each icon
+    icons: Vec<usize>,
+    blobs: Vec<Blob>,
+    runtime: Vec<&'static str>,
+}
+
+pub fn inspect(path: &Path) -> Result<(), PackError> {
+    let html = fs::read_to_string(path).map_err(|source| PackError::AssetRead {
+        path: path.to_path_buf(),
+        key: None,
+        source,
+    })?;
+    let page = read_page(&html).map_err(|e| PackError::Encoding {
+        id: path.display().to_string(),
+        key: None,
+        message: format!("cannot read html: {}", e),
+    })?;
+
+    println!("📦 {}  {}", path.display(), human_size(html.len()));
+    println!("{:<10} {}", "title", page.title.trim());
+    println!("{:<10} {}", "runtime", match page.runtime.is_empty() {
+        true => "none".to_string(),
+        false => page.runtime.join(", "),
+    });
+    let summary = |sizes: &[usize]| format!(
+        "{}, {}", sizes.len(), human_size(sizes.iter().sum())
+    );
+    println!("{:<10} {}", "styles", summary(&page.styles));
+    println!("{:<10} {}", "scripts", summary(&page.scripts));
+    println!("{:<10} {}", "icons", summary(&page.icons));
+    let blob_sizes: Vec<usize> = page.blobs.iter().map(|blob| blob.size).collect();
+    println!("{:<10} {}", "blobs", summary(&blob_sizes));
+    if page.blobs.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    println!(
+        "  {:<20} {:<18} {:<9} {:<12} {:>10}  sha256",
+        "id", "kind", "encoding", "compression", "size",
+    );
+    for blob in &page.blobs {
+        println!(
+            "  {:<20} {:<18} {:<9} {:<12} {:>10}  {}",
+            blob.id, blob.kind, blob.encoding, blob.compression,
+            human_size(blob.size), blob.hash.get(..16).unwrap_or(&blob.hash),
+        );
+    }
+    Ok(())
+}
+
+fn read_page(html: &str) -> Result<Page, lol_html::errors::RewritingError> {
+    let page = RefCell::new(Page::default());
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(html::BLOB_SELECTOR, |el| {
+                let attribute = |name: &str| el.get_attribute(name).unwrap_or_default();
+                let id = attribute("id");
+                let kind = if let Some(name) = el.get_attribute("data-wasm") {
+                    format!("wasm app {}", name)
+                } else if id == "bin-wasm-decoder" {
+                    page.borrow_mut().runtime.push("decoder");
+                    "runtime decoder".to_string()
+                } else if id == "bin-wasm-app" {
+                    "wasm app".to_string()
+                } else if el.has_attribute("data-asset") {
+                    attribute("mime")
+                } else {
+                    "blob".to_string()
+                };
+                page.borrow_mut().blobs.push(Blob {
+                    id,
+                    kind,
+                    encoding: el.get_attribute("encoding").unwrap_or("base64".to_string()),
+                    compression: el.get_attribute("compression").unwrap_or("none".to_string()),
+                    hash: attribute("hash"),
+                    ..Default::default()
+                });
+                Ok(())
+            }),
+            text!(html::BLOB_SELECTOR, |chunk| {
+                if let Some(blob) = page.borrow_mut().blobs.last_mut() {
+                    blob.text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+            element!("style", |_| {
+                page.borrow_mut().styles.push(0);
+                Ok(())
+            }),
+            text!("style", |chunk| {
+                if let Some(size) = page.borrow_mut().styles.last_mut() {
+                    *size += chunk.as_str().len();
+                }
+                Ok(())
+            }),
+            element!("script", |_| {
+                page.borrow_mut().scripts.push(0);
+                Ok(())
+            }),
+            text!("script", |chunk| {
+                let mut page = page.borrow_mut();
+                // the loading screen and the asset api live in core.js
+                if chunk.as_str().contains("window.setupWasm") && !page.runtime.contains(&"core") {
+                    page.runtime.insert(0, "core");
+                }
+                if let Some(size) = page.scripts.last_mut() {
+                    *size += chunk.as_str().len();
+                }
+                Ok(())
+            }),
+            element!("link[rel*=icon][href]", |el| {
+                let size = el.get_attribute("href").map_or(0, |href| href.len());
+                page.borrow_mut().icons.push(size);
+                Ok(())
+            }),
+            text!("title", |chunk| {
+                page.borrow_mut().title.push_str(chunk.as_str());
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    })?;
+    let mut page = page.into_inner();
+    for blob in &mut page.blobs {
+        blob.size = html::blob_text(&blob.text).len();
+        blob.text = String::new();
+    }
+    Ok(page)
+}
+
+pub fn human_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1048576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
+    }
+}
@@ -7,18 +7,32 @@
 
 // private modules
 mod cli;
+mod css;
+mod favicon;
+mod fonts;
+mod fragment;
 mod html;
+mod init;
+mod inspect;
+mod minify;
+mod pin;
+mod selfextract;
+mod unpack;
+mod validate;
 mod wasmbuilder;
+mod watch;
 
 // public modules
 pub mod config;
 pub mod encoder;
+pub mod error;
 pub mod fetcher;
 pub mod packer;
+pub mod report;
 
-
-
-//export htmlpacker::packer{load_config, pack};
+// the library api
+pub use error::PackError;
+pub use packer::{Packer, load_config, pack};
 
 
 //use ::htmlpacker::encoder;
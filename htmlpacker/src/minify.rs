@@ -0,0 +1,80 @@
+/*
+* minify.rs
+*
+* `minify: { js: true, css: true, html: true }`
+* minify-js for scripts, lightningcss for styles, minify-html for fragments
+* and entry documents, with their inline scripts and styles when asked to
+* whatever a minifier cannot handle is packed as it was, with a warning
+*/
+
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use minify_js::{Session, TopLevelMode};
+
+use crate::config::MinifyConfig;
+
+// by the kind the size report files it under, only what the config asks for
+pub fn text(source: String, kind: &str, name: &str, minify: &MinifyConfig) -> String {
+    match kind {
+        "js" if minify.js => js(&source, name),
+        "css" if minify.css => css(&source, name),
+        "html" if minify.html => html(&source, name, minify),
+        _ => source,
+    }
+}
+
+pub fn js(source: &str, name: &str) -> String {
+    let session = Session::new();
+    let mut out = Vec::new();
+    // global mode, top level names are shared with the other scripts
+    match minify_js::minify(&session, TopLevelMode::Global, source.as_bytes(), &mut out) {
+        Ok(()) => smaller(source, out, name),
+        Err(e) => {
+            eprintln!("⚠️  cannot minify {}, packed as is: {}", name, e);
+            source.to_string()
+        }
+    }
+}
+
+pub fn css(source: &str, name: &str) -> String {
+    let minified = StyleSheet::parse(source, ParserOptions::default())
+        .map_err(|e| e.to_string())
+        .and_then(|mut sheet| {
+            sheet.minify(MinifyOptions::default()).map_err(|e| e.to_string())?;
+            let options = PrinterOptions { minify: true, ..Default::default() };
+            sheet.to_css(options).map(|css| css.code).map_err(|e| e.to_string())
+        });
+    match minified {
+        Ok(css) => smaller(source, css.into_bytes(), name),
+        Err(e) => {
+            eprintln!("⚠️  cannot minify {}, packed as is: {}", name, e);
+            source.to_string()
+        }
+    }
+}
+
+// fragments are concatenated, so closing tags stay
+pub fn html(source: &str, name: &str, minify: &MinifyConfig) -> String {
+    let cfg = minify_html::Cfg {
+        keep_closing_tags: true,
+        keep_html_and_head_opening_tags: true,
+        do_not_minify_doctype: true,
+        ensure_spec_compliant_unquoted_attribute_values: true,
+        keep_spaces_between_attributes: true,
+        minify_js: minify.js,
+        minify_css: minify.css,
+        ..minify_html::Cfg::new()
+    };
+    smaller(source, minify_html::minify(source.as_bytes(), &cfg), name)
+}
+
+// a minifier can make tiny inputs longer, the original wins those
+fn smaller(source: &str, minified: Vec<u8>, name: &str) -> String {
+    match String::from_utf8(minified) {
+        Ok(minified) if minified.len() < source.len() => minified,
+        Ok(_) => source.to_string(),
+        Err(_) => {
+            eprintln!("⚠️  cannot minify {}, packed as is: not utf-8", name);
+            source.to_string()
+        }
+    }
+}
@@ -20,25 +20,52 @@ then encode it in base64
 
 use crate::config::{
     AssetSource, 
+    BinaryAsset,
+    CompressionType,
+    DecoderMode,
+    EncodingType,
+    FontConfig,
+    WasmApp,
     WasmModule, 
-    CompressionType, 
+    FaviconSetConfig,
+    FetchConfig,
+    MetaConfig,
+    MinifyConfig,
     PackerConfig,
     RuntimeConfig,
 };
-use crate::cli::{YamlRoot, Cli};
-use crate::encoder::{Base};
+use crate::cli::{YamlRoot, Cli, Command, ReportFormat, ReportOptions};
+use crate::css;
+use crate::fragment;
+use crate::encoder::{AppInfo, Base};
 use crate::encoder;
+use crate::error::PackError;
 use crate::wasmbuilder;
+use crate::watch;
 use crate::html;
-use crate::fetcher;
+use crate::init;
+use crate::inspect;
+use crate::minify;
+use crate::pin;
+use crate::report::SizeReport;
+use crate::selfextract;
+use crate::unpack;
+use crate::validate;
+use crate::favicon;
+use crate::favicon::Icon;
+use crate::fetcher::Fetcher;
+use crate::fonts;
 
 //
-use std::error::Error;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use clap::{Parser};
-use base64::prelude::*;
+use clap::{CommandFactory, Parser};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use sha2::{Sha256, Digest};
 
 // runtime assets on default
@@ -46,239 +73,1045 @@ const RUNTIME_ICON: &str = include_str!("../core/icon.svg");
 const RUNTIME_CORE_JS: &str = include_str!("../core/core.js");
 const RUNTIME_DECODER_JS: &str = include_str!("../core/wasm_decoder.js");
 const RUNTIME_DECODER_WASM: &[u8] = include_bytes!("../core/wasm_decoder_bg.wasm");
+// read by core.js, the wasm decoder is only loaded when the browser cannot decode
+const RUNTIME_DECODER_AUTO: &str =
+    "window.htmlpacker = Object.assign(window.htmlpacker || {}, { decoder: 'auto' });";
+// pins every remote asset, next to the config or in the working dir
+const LOCKFILE: &str = "htmlpacker.lock";
 
 // read yaml from file
 // set config from yaml
 // need serde structs
 
-// the basic run for API
-pub async fn run() -> Result<(), Box<dyn Error>> {
+// the basic run for the cli
+pub async fn run() -> Result<(), PackError> {
     // parse CLI
     let cli = Cli::parse();
-    println!("Config: {}", cli.config.display());
-    println!("Output: {}", cli.output.display());
-    
-    let config = load_config(cli.config).await?;
-    pack(config, cli.output).await?;
+    match (cli.command, cli.config) {
+        (Some(Command::Build { config, output, pin }), _) => {
+            build(config, output, pin, cli.offline, cli.reproducible, &cli.report).await?;
+        }
+        (Some(Command::Init { dir, force }), _) => {
+            init::init(&dir, force)?;
+        }
+        (Some(Command::Validate { config }), _) => {
+            validate::validate(&config).await?;
+        }
+        (Some(Command::Inspect { file }), _) => {
+            inspect::inspect(&file)?;
+        }
+        (Some(Command::Unpack { file, dir }), _) => {
+            unpack::unpack(&file, &dir)?;
+        }
+        (Some(Command::Pack { entry, output, runtime }), _) => {
+            let report = cli.report;
+            println!("Entry: {}", entry.display());
+            println!("Output: {}", output.display());
+            let config = PackerConfig {
+                entry: Some(AssetSource::Local(entry)),
+                fetch: FetchConfig {
+                    offline: cli.offline,
+                    lockfile: Some(PathBuf::from(LOCKFILE)),
+                    ..Default::default()
+                },
+                runtime: if runtime {
+                    RuntimeConfig::default()
+                } else {
+                    RuntimeConfig {
+                        enabled: false,
+                        icon: false,
+                        core: false,
+                        decoder: DecoderMode::Native,
+                    }
+                },
+                reproducible: cli.reproducible,
+                ..Default::default()
+            };
+            pack_and_report(config, output, &report).await?;
+        }
+        (Some(Command::Watch { config, output, port }), _) => {
+            watch::watch(config, output, port, cli.offline).await?;
+        }
+        (Some(Command::Check { config, expect }), _) => {
+            check(config, expect, cli.offline).await?;
+        }
+        // the old form, before there were subcommands
+        (None, Some(config_path)) => {
+            build(
+                config_path, cli.output, cli.pin, cli.offline, cli.reproducible, &cli.report,
+            ).await?;
+        }
+        (None, None) => {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "give a YAML config or a subcommand",
+                )
+                .exit();
+        }
+    }
+    Ok(())
+}
+
+// `htmlpacker build config.yaml`, or `--pin` to only pin its remote entries
+async fn build(
+    config_path: PathBuf,
+    output: PathBuf,
+    pin: bool,
+    offline: bool,
+    reproducible: bool,
+    report: &ReportOptions,
+) -> Result<(), PackError> {
+    if pin {
+        pin::pin_config(&config_path, offline).await?;
+        return Ok(());
+    }
+    println!("Config: {}", config_path.display());
+    println!("Output: {}", output.display());
+    let mut config = load_config(config_path).await?;
+    config.fetch.offline |= offline;
+    config.reproducible |= reproducible;
+    pack_and_report(config, output, report).await
+}
+
+// check ------------------------------------------------------------------- /
+// pack the config twice, the way an auditor would, and compare byte for byte
+// with expect, the rebuilt file also has to match one that was shipped
+async fn check(
+    config_path: PathBuf,
+    expect: Option<PathBuf>,
+    offline: bool,
+) -> Result<(), PackError> {
+    let mut packs = Vec::new();
+    for _ in 0..2 {
+        let mut config = load_config(config_path.clone()).await?;
+        config.fetch.offline |= offline;
+        config.reproducible = true;
+        packs.push(Packer::new(config).pack_to_string().await?);
+    }
+    let target = config_path.display().to_string();
+    if let Some(message) = first_difference(&packs[0], &packs[1]) {
+        return Err(PackError::NotReproducible {
+            target,
+            message: format!("two packs differ {}", message),
+        });
+    }
+    let hash = format!("{:x}", Sha256::digest(packs[0].as_bytes()));
+    println!("✅ {} packs byte-identical ({} bytes, sha256 {})", target, packs[0].len(), hash);
+
+    if let Some(expect) = expect {
+        let shipped = fs::read(&expect).map_err(|source| PackError::AssetRead {
+            path: expect.clone(),
+            key: None,
+            source,
+        })?;
+        let shipped = String::from_utf8_lossy(&shipped);
+        if let Some(message) = first_difference(&shipped, &packs[0]) {
+            return Err(PackError::NotReproducible {
+                target: expect.display().to_string(),
+                message: format!("it differs from {} {}", target, message),
+            });
+        }
+        println!("✅ {} matches {}", expect.display(), target);
+    }
     Ok(())
 }
 
+// where two documents part ways, with a bit of each side
+fn first_difference(a: &str, b: &str) -> Option<String> {
+    let offset = a.bytes().zip(b.bytes()).position(|(x, y)| x != y)
+        .or((a.len() != b.len()).then(|| a.len().min(b.len())))?;
+    let line = a.as_bytes()[..offset].iter().filter(|&&c| c == b'\n').count() + 1;
+    let excerpt = |text: &str| {
+        let bytes = &text.as_bytes()[offset..text.len().min(offset + 60)];
+        format!("{:?}", String::from_utf8_lossy(bytes))
+    };
+    Some(format!(
+        "at byte {} (line {}), {} bytes against {}\n  first  {}\n  second {}",
+        offset, line, a.len(), b.len(), excerpt(a), excerpt(b),
+    ))
+}
+
+// SOURCE_DATE_EPOCH, seconds since 1970, as used by reproducible-builds.org
+fn source_date_epoch() -> Result<Option<SystemTime>, PackError> {
+    let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") else {
+        return Ok(None);
+    };
+    let seconds: u64 = epoch.trim().parse().map_err(|_| PackError::Unsupported {
+        key: None,
+        message: format!("SOURCE_DATE_EPOCH '{}' is not a number of seconds", epoch),
+    })?;
+    Ok(Some(UNIX_EPOCH + Duration::from_secs(seconds)))
+}
+
 // loads config from given path, serde yaml->config magic
 pub async fn load_config(
     config_path: PathBuf,
-) -> Result<PackerConfig, Box<dyn Error>> {
-    let yaml_text = fs::read_to_string(config_path)?;
-    let yaml_root: YamlRoot = serde_yaml::from_str(&yaml_text)?;
+) -> Result<PackerConfig, PackError> {
+    let yaml_text = fs::read_to_string(&config_path)
+        .map_err(|source| PackError::ConfigRead {
+            path: config_path.clone(),
+            source,
+        })?;
+    let yaml_root: YamlRoot = serde_yaml::from_str(&yaml_text)
+        .map_err(|e| PackError::ConfigParse {
+            path: Some(config_path.clone()),
+            key: None,
+            message: e.to_string(),
+        })?;
     println!("Extracted yaml");
     //println!("{:?}", yaml_text);
     //println!("{:#?}", &yaml_root.pack);
-    let config = crate::cli::set_config_from_yaml(yaml_root.pack).await?;
+    let config = crate::cli::set_config_from_yaml(yaml_root.pack).await
+        .map_err(|e| match e {
+            PackError::ConfigParse { path: None, key, message } => {
+                PackError::ConfigParse {
+                    path: Some(config_path.clone()),
+                    key,
+                    message,
+                }
+            }
+            e => e,
+        })?;
+    // the lockfile lives next to the config unless told otherwise
+    let mut config = config;
+    if config.fetch.lockfile.is_none() {
+        config.fetch.lockfile = Some(config_path.with_file_name(LOCKFILE));
+    }
     println!("Loaded config from yaml");
     Ok(config)
 }
 
+// text minified when the config asks for it, the report gets both sizes
+fn pack_text(
+    report: &mut SizeReport,
+    minify: &MinifyConfig,
+    key: &str,
+    kind: &str,
+    text: String,
+) -> String {
+    let raw = text.len();
+    let text = minify::text(text, kind, key, minify);
+    // html::page does the escaping, this is only to say so
+    let escaped = match kind {
+        "js" => html::escape_script(&text),
+        "css" => html::escape_style(&text),
+        _ => Cow::Borrowed(text.as_str()),
+    };
+    if let Cow::Owned(_) = escaped {
+        let tag = if kind == "js" { "script" } else { "style" };
+        eprintln!("⚠️  {} has a closing tag in its text, it gets escaped so it cannot end its <{}> early", key, tag);
+    }
+    report.add_minified(key, kind, raw, &text);
+    text
+}
+
+pub fn native_codec_message(compression: CompressionType) -> String {
+    format!(
+        "{} needs the wasm decoder, pack it with gzip or deflate \
+        or set runtime.decoder to auto or wasm",
+        compression.as_str(),
+    )
+}
+
 // extremely wonky
 fn default_runtime(
     runtime: &RuntimeConfig,
-    icons: &mut Vec<String>,
+    icons: &mut Vec<Icon>,
     scripts: &mut Vec<String>,
     bin: &mut Vec<Base>,
-) {
+    report: &mut SizeReport,
+    minify: &MinifyConfig,
+) -> Result<(), PackError> {
     println!("Default runtime is enabled.");
 
-    // favicon
-    if runtime.icon {
+    // favicon, only when the config did not bring its own
+    if runtime.icon && icons.is_empty() {
         println!("Adding icon.");
-        let encoded_icon_string = 
-            BASE64_STANDARD.encode(RUNTIME_ICON.as_bytes());
-        icons.insert(0, encoded_icon_string);
-
+        let icon = Icon::from_bytes(RUNTIME_ICON.as_bytes(), Some("icon.svg"));
+        report.add_text("runtime.icon", icon.mime, &icon.href);
+        icons.push(icon);
     }
 
     // core script
+    // wasm and native can be told apart by the decoder blob, auto is written down
     if runtime.core {
         println!("Adding core.js");
-        scripts.push(RUNTIME_CORE_JS.to_string());
+        let core = match runtime.decoder {
+            DecoderMode::Auto => format!("{}\n{}", RUNTIME_DECODER_AUTO, RUNTIME_CORE_JS),
+            _ => RUNTIME_CORE_JS.to_string(),
+        };
+        scripts.push(pack_text(report, minify, "runtime.core.js", "js", core));
     }
     
-    // decoder js and wasm
-    if runtime.decoder {
-        println!("Adding decoder.");
-        scripts.push(RUNTIME_DECODER_JS.to_string());
+    // decoder js and wasm, native leaves both to the browser
+    if runtime.decoder != DecoderMode::Native {
+        println!("Adding {} decoder.", runtime.decoder.as_str());
+        let decoder = RUNTIME_DECODER_JS.to_string();
+        scripts.push(pack_text(report, minify, "runtime.decoder.js", "js", decoder));
         // decoder wasm binary
-        let wasm_hash = Sha256::digest(RUNTIME_DECODER_WASM);
-        let wasm_hash_string = format!("{:x}", wasm_hash);
-        let wasm_encoded_text = BASE64_STANDARD.encode(RUNTIME_DECODER_WASM);
-        let decoder_module = Base {
-            id: "bin-wasm-decoder".to_string(),
-            hash: wasm_hash_string,
-            text: wasm_encoded_text,
-        };
+        // always base64, the runtime needs it before the decoder exists
+        let decoder_module = encoder::encode_bytes(
+            RUNTIME_DECODER_WASM,
+            "bin-wasm-decoder",
+            CompressionType::None,
+            EncodingType::Base64,
+        )?;
+        report.add_blob("runtime.decoder.wasm", "runtime wasm", &decoder_module);
         bin.push(decoder_module);
     }
+    Ok(())
+}
+
+// packer ------------------------------------------------------------------ /
+// library entry point
+// start from a config (loaded from yaml or built by hand),
+// add any extra assets programmatically, then pack into memory or a sink
+#[derive(Debug, Default)]
+pub struct Packer {
+    config: PackerConfig,
+}
+
+impl Packer {
+    pub fn new(config: PackerConfig) -> Self {
+        Packer { config }
+    }
+
+    pub fn runtime(mut self, runtime: RuntimeConfig) -> Self {
+        self.config.runtime = runtime;
+        self
+    }
+
+    // pack an existing html document instead of generating the page
+    pub fn entry(mut self, source: AssetSource) -> Self {
+        self.config.entry = Some(source);
+        self
+    }
+
+    pub fn meta(mut self, meta: MetaConfig) -> Self {
+        self.config.meta = Some(meta);
+        self
+    }
+
+    pub fn favicon(mut self, source: AssetSource) -> Self {
+        self.config.favicon.get_or_insert_with(Vec::new).push(source);
+        self
+    }
+
+    pub fn favicon_set(mut self, favicon_set: FaviconSetConfig) -> Self {
+        self.config.favicon_set = Some(favicon_set);
+        self
+    }
+
+    pub fn style(mut self, source: AssetSource) -> Self {
+        self.config.styles.get_or_insert_with(Vec::new).push(source);
+        self
+    }
+
+    pub fn script(mut self, source: AssetSource) -> Self {
+        self.config.scripts.get_or_insert_with(Vec::new).push(source);
+        self
+    }
+
+    pub fn html(mut self, source: AssetSource) -> Self {
+        self.config.html.get_or_insert_with(Vec::new).push(source);
+        self
+    }
+
+    pub fn wasm(mut self, module: WasmModule) -> Self {
+        self.config.wasm.get_or_insert_with(Vec::new).push(module);
+        self
+    }
+
+    pub fn asset(mut self, asset: BinaryAsset) -> Self {
+        self.config.assets.get_or_insert_with(Vec::new).push(asset);
+        self
+    }
+
+    pub fn font(mut self, font: FontConfig) -> Self {
+        self.config.fonts.get_or_insert_with(Vec::new).push(font);
+        self
+    }
+
+    pub fn config(&self) -> &PackerConfig {
+        &self.config
+    }
+
+    // the packed document and where its bytes went
+    // budgets are not checked, see SizeReport::check_budgets
+    pub async fn pack_with_report(self) -> Result<(String, SizeReport), PackError> {
+        pack_document(self.config).await
+    }
+
+    // the packed document as a string, within its budgets
+    pub async fn pack_to_string(self) -> Result<String, PackError> {
+        let (html, report) = self.pack_with_report().await?;
+        report.check_budgets()?;
+        Ok(html)
+    }
+
+    // the packed document as raw utf-8 bytes
+    pub async fn pack_to_bytes(self) -> Result<Vec<u8>, PackError> {
+        Ok(self.pack_to_string().await?.into_bytes())
+    }
+
+    // stream the packed document into any writer
+    pub async fn pack_to_writer<W: Write>(
+        self,
+        writer: &mut W,
+    ) -> Result<(), PackError> {
+        let html = self.pack_to_string().await?;
+        let output_error = |source| PackError::Output {
+            target: "writer".to_string(),
+            source,
+        };
+        writer.write_all(html.as_bytes()).map_err(output_error)?;
+        writer.flush().map_err(output_error)?;
+        Ok(())
+    }
+
+    // write the packed document to disk, creating parent dirs
+    // reproducible packs date the file with SOURCE_DATE_EPOCH
+    pub async fn pack_to_file(
+        self,
+        output: impl AsRef<Path>,
+    ) -> Result<(), PackError> {
+        let output = output.as_ref().to_path_buf();
+        let date = match self.config.reproducible {
+            true => source_date_epoch()?,
+            false => None,
+        };
+        let html = self.pack_to_string().await?;
+        save_dated(html, &output, date)
+    }
+}
+
+fn save_dated(
+    html: String,
+    output: &Path,
+    date: Option<SystemTime>,
+) -> Result<(), PackError> {
+    html::save_html(html, output.to_path_buf())?;
+    if let Some(date) = date {
+        fs::File::options()
+            .write(true)
+            .open(output)
+            .and_then(|file| file.set_modified(date))
+            .map_err(|source| PackError::Output {
+                target: output.display().to_string(),
+                source,
+            })?;
+    }
+    Ok(())
 }
 
 // pack --------------------------------------------------------------------- /
-// this is the holy grail function 
-// have to separate pack from parse cli
 // pack takes in a config and an output filename
+// kept around as the short way to pack straight to disk
 pub async fn pack(
     config: PackerConfig,
     output: PathBuf,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), PackError> {
+    Packer::new(config).pack_to_file(output).await
+}
+
+// the same from the cli, with the size report shown before the budgets are checked
+// an asset over budget is easier to find with the whole table in view
+async fn pack_and_report(
+    config: PackerConfig,
+    output: PathBuf,
+    report: &ReportOptions,
+) -> Result<(), PackError> {
+    let date = match config.reproducible {
+        true => source_date_epoch()?,
+        false => None,
+    };
+    let (html, size_report) = Packer::new(config).pack_with_report().await?;
+    match report.format {
+        ReportFormat::Table => size_report.print_table(),
+        ReportFormat::Json => println!("{}", size_report.to_json()),
+        ReportFormat::None => (),
+    }
+    if let Some(path) = &report.file {
+        fs::write(path, size_report.to_json()).map_err(|source| PackError::Output {
+            target: path.display().to_string(),
+            source,
+        })?;
+    }
+    size_report.check_budgets()?;
+    save_dated(html, &output, date)
+}
+
+// this is the holy grail function 
+// everything in the config ends up in one html string
+// the size report comes back with it, budgets are left to the caller
+async fn pack_document(
+    mut config: PackerConfig,
+) -> Result<(String, SizeReport), PackError> {
+    // nothing left to chance: no unpinned downloads, no lockfile updates
+    if config.reproducible {
+        config.fetch.frozen = true;
+        let compiled = config.wasm.iter().flatten().any(|module| module.compile_wasm);
+        if compiled {
+            eprintln!("⚠️  compile_wasm output depends on the local toolchain, \
+                reproducible builds should pack prebuilt modules");
+        }
+    }
+
+    // without the wasm decoder only gzip and deflate are safe to pack
+    if let Some((key, compression)) = config.native_codec_conflicts().into_iter().next() {
+        return Err(PackError::Unsupported {
+            key: Some(key),
+            message: native_codec_message(compression),
+        });
+    }
+    let codecs = match config.runtime.enabled {
+        true => config.runtime.decoder.codecs(),
+        false => &CompressionType::CODECS,
+    };
+
     // make sure to compile our wasm binaries and js glue first
     // how to disable this if already done?
     if let Some(ref modules) = config.wasm {
         wasmbuilder::compile_wasm_modules(modules).await?;
     }
-    
-    // favicon multiple allowed but forcing only one supported rn
-    let icon_sources = match config.favicon {
-        Some(source) => get_icons(source).await?,
-        None => vec![],
+
+    // every remote file of this pack goes through the cache and lockfile
+    let fetcher = Fetcher::new(&config.fetch)?;
+    let fetcher = &fetcher;
+    let mut report = SizeReport::new(config.budgets.take());
+
+    // every section reads files or hits the network, so they all run at once
+    // each one still comes back in config order
+    let (favicon, styles, scripts, html_sources, entry, wasm, assets) = (
+        config.favicon.take(),
+        config.styles.take(),
+        config.scripts.take(),
+        config.html.take(),
+        config.entry.take(),
+        config.wasm.take(),
+        config.assets.take(),
+    );
+    // the yaml key of every text source, for the report
+    let keys = |section: &str, sources: &Option<Vec<AssetSource>>| {
+        asset_keys(section, sources.as_deref().unwrap_or_default())
     };
+    let (style_keys, script_keys, html_keys) = (
+        keys("css", &styles),
+        keys("scripts", &scripts),
+        keys("html", &html_sources),
+    );
+    let favicon_set = config.favicon_set.as_ref();
+    let (
+        mut icons,
+        styles,
+        scripts,
+        (html_texts, fragment_styles),
+        entry,
+        (wasm, glue),
+        assets,
+    ) =
+        tokio::try_join!(
+            // favicons as they are, or the full set generated from the first one
+            async move {
+                match favicon {
+                    Some(source) => get_icons(source, favicon_set, fetcher).await,
+                    None => Ok(vec![]),
+                }
+            },
+            // styles as one big string
+            async move {
+                match styles {
+                    Some(source) => get_styles_text(source, fetcher).await,
+                    None => Ok(vec![]),
+                }
+            },
+            // scripts as a vec
+            async move {
+                match scripts {
+                    Some(source) => get_sources(source, "scripts", fetcher).await,
+                    None => Ok(vec![]),
+                }
+            },
+            // html fragments and the stylesheets they link to
+            async move {
+                match html_sources {
+                    Some(source) => get_html_texts(source, fetcher).await,
+                    None => Ok((vec![], "".to_string())),
+                }
+            },
+            // the entry document, with everything it references inlined in place
+            async move {
+                match entry {
+                    Some(source) => get_entry(source, fetcher).await.map(Some),
+                    None => Ok(None),
+                }
+            },
+            // binary wasm files
+            async move {
+                match wasm {
+                    Some(modules) => get_wasm(wasmbuilder::order_wasm_modules(modules)?, codecs, fetcher).await,
+                    None => Ok((vec![], vec![])),
+                }
+            },
+            // binary assets, loaded lazily by the runtime
+            async move {
+                match assets {
+                    Some(assets) => get_assets(assets, codecs, fetcher).await,
+                    None => Ok(vec![]),
+                }
+            },
+        )?;
 
-    // this is sort of good but also SLOP
-    let mut icons = vec![];
-    if !icon_sources.is_empty() {
-        icons.push(icon_sources[0].clone());
+    for (key, html_text) in html_keys.iter().zip(&html_texts) {
+        for problem in fragment::unbalanced(html_text) {
+            eprintln!("⚠️  {}: {}", key, problem);
+        }
     }
-    
-    //styles as one big string
-    //let styles_text = get_styles_text(config.styles).await?;
-    let styles_text = match config.styles {
-        Some(source) => get_styles_text(source).await?,
-        None => "".to_string(),
-    };
-    
-    // scripts as a vec
-    let mut scripts = match config.scripts {
-        Some(source) => get_sources(source).await?,
-        None => vec![],
-    };
 
-    //let html_text = "<p>test</p>".to_string();
-    let html_texts = match config.html {
-        Some(source) => get_sources(source).await?,
-        None => vec![],
-    };
-    println!("{:?}", html_texts);
+    let minify = config.minify;
+    let styles: Vec<String> = style_keys.iter().zip(styles)
+        .map(|(key, style)| pack_text(&mut report, &minify, key, "css", style))
+        .collect();
+    let mut scripts: Vec<String> = script_keys.iter().zip(scripts)
+        .map(|(key, script)| pack_text(&mut report, &minify, key, "js", script))
+        .collect();
+    let html_texts: Vec<String> = html_keys.iter().zip(html_texts)
+        .map(|(key, html_text)| pack_text(&mut report, &minify, key, "html", html_text))
+        .collect();
+    let fragment_styles = pack_text(&mut report, &minify, "html stylesheets", "css", fragment_styles);
+    let entry = entry.map(|mut entry| {
+        entry.html = pack_text(&mut report, &minify, "entry", "html", entry.html);
+        entry
+    });
+    let icon_bytes: usize = icons.iter().map(|icon| icon.href.len()).sum();
+    if icon_bytes > 0 {
+        report.add("favicon", "icon", icon_bytes, icon_bytes, icon_bytes);
+    }
 
-    // this is super brittle
-    // we don't do it
-    // just add wasm_decoder pkg to core
-    /*
-    if !scripts.is_empty() {
-        // rename wasm_bindgen so that we don't have double definition conflicts
-        scripts[0] = scripts[0].replace("wasm_bindgen", "wasm_decoder");
+    // linked stylesheets found in the fragments join the style block
+    let mut styles_text = styles.concat();
+    styles_text.push_str(&fragment_styles);
+
+    // fonts go in front of the styles so they can use them
+    if let Some(fonts) = config.fonts.take() {
+        let mut texts = html_texts.clone();
+        texts.extend(entry.iter().map(|entry| entry.html.clone()));
+        let font_faces = get_font_faces(fonts, &texts, fetcher).await?;
+        for (i, font_face) in font_faces.iter().enumerate() {
+            report.add_text(&format!("fonts[{}]", i), "font", font_face);
+        }
+        styles_text.insert_str(0, &font_faces.concat());
     }
-    */
 
-    // binary wasm files
-    //let bin = get_wasm(config.wasm)?;
-    let mut bin = match config.wasm {
-        Some(source) => get_wasm(source)?,
-        None => vec![],
-    };
+    // the wasm apps' glue runs after the user scripts
+    for (key, glue) in glue {
+        scripts.push(pack_text(&mut report, &minify, &key, "js", glue));
+    }
+
+    // binary wasm files, then the assets
+    for base in &wasm {
+        let name = match &base.app {
+            Some(app) => format!("wasm.{}", app.name),
+            None => format!("wasm.{}", base.id),
+        };
+        report.add_blob(&name, "wasm", base);
+    }
+    for base in &assets {
+        report.add_blob(&format!("assets.{}", base.id), base.mime.as_deref().unwrap_or("asset"), base);
+    }
+    let mut bin = wasm;
+    bin.extend(assets);
 
     // set default runtime for the given configuration
+    // an entry document with its own favicon keeps it
     if config.runtime.enabled {
+        let entry_has_icon = entry.as_ref().is_some_and(|entry| entry.has_icon);
         default_runtime(
-            &config.runtime,
+            &RuntimeConfig {
+                icon: config.runtime.icon && !entry_has_icon,
+                ..config.runtime
+            },
             &mut icons,
             &mut scripts,
             &mut bin,
-        );
+            &mut report,
+            &minify,
+        )?;
     }
 
+    let mut document = match entry {
+        Some(entry) => html::entry_page(
+            &entry.html,
+            styles_text,
+            icons,
+            html_texts,
+            scripts,
+            bin,
+        )?,
+        None => {
+            // preview image for link previews, as a data uri
+            let preview_image = match config.meta.as_ref().and_then(|m| m.image.as_ref()) {
+                Some(source) => Some(get_data_uri(source, "meta.image", fetcher).await?),
+                None => None,
+            };
+            if let Some(image) = &preview_image {
+                report.add_text("meta.image", "image", image);
+            }
 
-    let markup = html::page(
-        styles_text,
-        icons,
-        html_texts,
-        scripts,
-        bin,
-    );
+            html::page(
+                styles_text,
+                icons,
+                html_texts,
+                scripts,
+                bin,
+                html::PageMeta {
+                    meta: config.meta.as_ref(),
+                    image: preview_image,
+                },
+            ).into_string()
+        }
+    };
 
-    let html = markup.into_string();
-    //println!("html: {}", html);
-    html::save_html(html, output)?;
+    if config.self_extracting {
+        let page = selfextract::self_extracting(&document, &minify, &mut report)?;
+        println!("Self extracting: {} -> {} bytes", document.len(), page.len());
+        if page.len() > document.len() {
+            eprintln!("⚠️  self_extracting made the page larger, there is too little text to compress");
+        }
+        document = page;
+    }
 
-    Ok(())
+    // only a successful pack gets to update the lockfile
+    fetcher.save_lock()?;
+    report.finish(document.len());
+    Ok((document, report))
 }
 
 async fn get_icons(
-    icon_sources: Vec<AssetSource> 
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut icons: Vec<String> = vec![];
-    for icon in icon_sources {
-        // we get the path of the file
-        // must be local (for now)
-        let path_str = match icon {
-            AssetSource::Local(path) => path.to_str().ok_or("Invalid favicon path")?.to_string(),
-            AssetSource::Remote(_) => return Err("Remote favicons not yet supported".into()),
+    icon_sources: Vec<AssetSource>,
+    favicon_set: Option<&FaviconSetConfig>,
+    fetcher: &Fetcher,
+) -> Result<Vec<Icon>, PackError> {
+    let keys = asset_keys("favicon", &icon_sources);
+
+    // the set is generated from the first source only
+    if let Some(set) = favicon_set {
+        let Some((icon, key)) = icon_sources.iter().zip(keys).next() else {
+            return Ok(vec![]);
         };
-        // then we get the buffer and encode
-        let encoded_icon = crate::encoder::encode_base64(&path_str, "favicon")?;
-        icons.push(encoded_icon.text);
+        let (buffer, name) = get_source_bytes(icon, &key, fetcher).await?;
+        println!("Generating favicon set from {}", key);
+        return favicon::generate_set(&buffer, name.as_deref(), &set.mask_color)
+            .map_err(|e| e.with_key(&key));
     }
-    Ok(icons)
+
+    stream::iter(icon_sources.iter().zip(keys))
+        .map(|(icon, key)| async move {
+            let (buffer, name) = get_source_bytes(icon, &key, fetcher).await?;
+            Ok(Icon::from_bytes(&buffer, name.as_deref()))
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await
 }
 
-// append each css file together
+// each css file with its url() and @import inlined
 async fn get_styles_text(
-    style_sources: Vec<AssetSource>
-) -> Result<String, Box<dyn Error>> {
-    //let styles_text = fetcher::get_css_string(css_urls).await?;
-    // init empty string
-    let mut styles_text = String::from("");
-    for source in style_sources {
-        let text = match source {
-            AssetSource::Local(path) => fetcher::get_local_file(&path)?,
-            AssetSource::Remote(url) => fetcher::get_remote_file(url).await?,
-        };
-        // append
-        styles_text.push_str(&text);
-    }
-    Ok(styles_text)
+    style_sources: Vec<AssetSource>,
+    fetcher: &Fetcher,
+) -> Result<Vec<String>, PackError> {
+    let keys = asset_keys("css", &style_sources);
+    stream::iter(style_sources.into_iter().zip(keys))
+        .map(|(source, key)| async move {
+            let origin = css::Origin::of(&source);
+            let text = get_source_text(source, &key, fetcher).await?;
+            // url() and @import are resolved against the stylesheet itself
+            css::inline_css(&text, &origin, fetcher)
+                .await
+                .map_err(|e| e.with_key(&key))
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await
 }
 
 async fn get_sources(
-    sources: Vec<AssetSource>
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut source_text_list: Vec<String> = vec![];
-    for source in sources {
-        let text = match source {
-            AssetSource::Local(path) => fetcher::get_local_file(&path)?,
-            AssetSource::Remote(url) => fetcher::get_remote_file(url).await?,
-        };
-        //append
-        source_text_list.push(text);
+    sources: Vec<AssetSource>,
+    section: &str,
+    fetcher: &Fetcher,
+) -> Result<Vec<String>, PackError> {
+    let keys = asset_keys(section, &sources);
+    stream::iter(sources.into_iter().zip(keys))
+        .map(|(source, key)| async move {
+            get_source_text(source, &key, fetcher).await
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await
+}
+
+// html fragments with everything they reference inlined
+// returns the fragments and the stylesheets they linked to
+async fn get_html_texts(
+    sources: Vec<AssetSource>,
+    fetcher: &Fetcher,
+) -> Result<(Vec<String>, String), PackError> {
+    let keys = asset_keys("html", &sources);
+    let fragments: Vec<fragment::Fragment> = stream::iter(sources.into_iter().zip(keys))
+        .map(|(source, key)| async move {
+            let origin = css::Origin::of(&source);
+            let text = get_source_text(source, &key, fetcher).await?;
+            fragment::inline_fragment(
+                &text,
+                &origin,
+                &key,
+                fragment::Stylesheets::Extract,
+                fetcher,
+            )
+                .await
+                .map_err(|e| e.with_key(&key))
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await?;
+    let mut html_texts: Vec<String> = vec![];
+    let mut styles_text = String::from("");
+    for fragment in fragments {
+        html_texts.push(fragment.html);
+        for style in fragment.styles {
+            styles_text.push_str(&style);
+        }
     }
-    Ok(source_text_list)
+    Ok((html_texts, styles_text))
 }
 
-fn get_wasm(
-    wasm_modules: Vec<WasmModule> 
-) -> Result<Vec<Base>, Box<dyn Error>> {
-    let mut bin: Vec<Base> = vec![];
-    for module in wasm_modules {
-        // we get the path of the file
-        // must be local (for now)
-        let path_str = match module.source{
-            AssetSource::Local(path) => path.to_str().ok_or("Invalid WASM path")?.to_string(),
-            AssetSource::Remote(_) => return Err("Remote WASM modules not yet supported".into()),
-        };
-        // then we get the buffer and encode
-        let encoded_module = match module.compression {
-            CompressionType::Brotli => encoder::encode_brotli_base64(&path_str, &module.id)?,
-            CompressionType::None => encoder::encode_base64(&path_str, &module.id)?,
-        };
-        bin.push(encoded_module);
+// the entry document keeps its stylesheets where they are
+async fn get_entry(
+    source: AssetSource,
+    fetcher: &Fetcher,
+) -> Result<fragment::Fragment, PackError> {
+    let origin = css::Origin::of(&source);
+    let text = get_source_text(source, "entry", fetcher).await?;
+    fragment::inline_fragment(
+        &text,
+        &origin,
+        "entry",
+        fragment::Stylesheets::InPlace,
+        fetcher,
+    )
+        .await
+        .map_err(|e| e.with_key("entry"))
+}
+
+// read any text source into a string
+async fn get_source_text(
+    source: AssetSource,
+    key: &str,
+    fetcher: &Fetcher,
+) -> Result<String, PackError> {
+    let text = match source {
+        AssetSource::Local(path) => fetcher.get_local_text(&path).await,
+        AssetSource::Remote(url) => fetcher.get_remote_text(&url).await,
+        AssetSource::Inline(bytes) => String::from_utf8(bytes)
+            .map_err(|e| PackError::Encoding {
+                id: key.to_string(),
+                key: None,
+                message: e.to_string(),
+            }),
+    };
+    text.map_err(|e| e.with_key(key))
+}
+
+// every binary asset becomes a blob with its mime type attached
+async fn get_assets(
+    assets: Vec<BinaryAsset>,
+    codecs: &[CompressionType],
+    fetcher: &Fetcher,
+) -> Result<Vec<Base>, PackError> {
+    stream::iter(assets)
+        .map(|asset| async move {
+            let key = format!("assets.{}", asset.id);
+            let (buffer, name) = get_source_bytes(&asset.source, &key, fetcher).await?;
+            let mime = match asset.mime {
+                Some(mime) => mime,
+                None => encoder::sniff_mime(&buffer, name.as_deref()).to_string(),
+            };
+            let mut encoded_asset = encoder::encode_bytes_with_level(
+                &buffer,
+                &asset.id,
+                asset.compression,
+                asset.level,
+                asset.encoding,
+                codecs,
+            )?;
+            encoded_asset.mime = Some(mime);
+            Ok(encoded_asset)
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await
+}
+
+// one @font-face rule per font, subset first if asked to
+async fn get_font_faces(
+    fonts: Vec<FontConfig>,
+    html_texts: &[String],
+    fetcher: &Fetcher,
+) -> Result<Vec<String>, PackError> {
+    stream::iter(fonts.iter().enumerate())
+        .map(|(i, font)| async move {
+            get_font_face(font, &format!("fonts[{}]", i), html_texts, fetcher).await
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await
+}
+
+async fn get_font_face(
+    font: &FontConfig,
+    key: &str,
+    html_texts: &[String],
+    fetcher: &Fetcher,
+) -> Result<String, PackError> {
+    let (mut buffer, mut name) = get_source_bytes(&font.source, key, fetcher).await?;
+    if let Some(subset) = &font.subset {
+        let mut chars = BTreeSet::new();
+        if let Some(keep) = &subset.chars {
+            chars.extend(keep.chars());
+        }
+        if subset.used {
+            chars.extend(fonts::used_chars(html_texts));
+        }
+        let before = buffer.len();
+        buffer = fonts::subset_font(&buffer, &chars, &font.family)
+            .map_err(|e| e.with_key(key))?;
+        // the subset is always a plain sfnt, let the mime sniffing see that
+        name = None;
+        println!(
+            "Subset font {} to {} chars: {} -> {} bytes",
+            font.family, chars.len(), before, buffer.len(),
+        );
     }
-    Ok(bin)
+    Ok(fonts::font_face(font, &buffer, name.as_deref()))
+}
+
+// read any source as bytes, plus a file name to guess the mime type from
+async fn get_source_bytes(
+    source: &AssetSource,
+    key: &str,
+    fetcher: &Fetcher,
+) -> Result<(Vec<u8>, Option<String>), PackError> {
+    let read = match source {
+        AssetSource::Local(path) => fetcher.get_local_bytes(path).await
+            .map(|bytes| (bytes, path.to_str().map(String::from))),
+        AssetSource::Remote(url) => fetcher.get_remote_bytes(url).await
+            .map(|bytes| (bytes, Some(url.path().to_string()))),
+        AssetSource::Inline(bytes) => Ok((bytes.clone(), None)),
+    };
+    read.map_err(|e| e.with_key(key))
 }
 
+// read any source as bytes and embed it as a data uri
+async fn get_data_uri(
+    source: &AssetSource,
+    key: &str,
+    fetcher: &Fetcher,
+) -> Result<String, PackError> {
+    let (buffer, name) = get_source_bytes(source, key, fetcher).await?;
+    let mime = encoder::sniff_mime(&buffer, name.as_deref());
+    Ok(encoder::data_uri(&buffer, mime))
+}
+
+// the yaml key each source came from, like scripts.local[0]
+// cli.rs puts the local entries before the remote ones
+fn asset_keys(section: &str, sources: &[AssetSource]) -> Vec<String> {
+    let (mut local, mut remote, mut inline) = (0, 0, 0);
+    sources.iter()
+        .map(|source| {
+            let (kind, i) = match source {
+                AssetSource::Local(_) => ("local", &mut local),
+                AssetSource::Remote(_) => ("remote", &mut remote),
+                AssetSource::Inline(_) => ("inline", &mut inline),
+            };
+            let key = format!("{}.{}[{}]", section, kind, i);
+            *i += 1;
+            key
+        })
+        .collect()
+}
+
+// local or downloaded, every module goes through the same encoder path
+// apps also bring back their glue, wrapped in its own namespace
+// and keyed like wasm.<name>.glue
+async fn get_wasm(
+    wasm_modules: Vec<WasmModule>,
+    codecs: &[CompressionType],
+    fetcher: &Fetcher,
+) -> Result<(Vec<Base>, Vec<(String, String)>), PackError> {
+    let modules: Vec<(Base, Option<(String, String)>)> = stream::iter(wasm_modules)
+        .map(|mut module| async move {
+            let key = match &module.app {
+                Some(app) => format!("wasm.{}", app.name),
+                None => format!("wasm.{}", module.id),
+            };
+            let glue = module.app.as_mut().and_then(|app| app.glue.take());
+            let ((buffer, _), glue) = tokio::try_join!(
+                get_source_bytes(&module.source, &key, fetcher),
+                async {
+                    match glue {
+                        Some(glue) => get_source_text(glue, &format!("{}.glue", key), fetcher)
+                            .await
+                            .map(Some),
+                        None => Ok(None),
+                    }
+                },
+            )?;
+            // then we compress and encode the buffer
+            let mut base = encoder::encode_bytes_with_level(
+                &buffer,
+                &module.id,
+                module.compression,
+                module.level,
+                module.encoding,
+                codecs,
+            ).map_err(|e| e.with_key(&key))?;
+            let Some(app) = module.app else {
+                return Ok((base, None));
+            };
+            let args = match app.args.is_empty() {
+                true => None,
+                false => Some(serde_json::Value::from(app.args.clone()).to_string()),
+            };
+            base.app = Some(AppInfo {
+                name: app.name.clone(),
+                global: glue.is_none().then(|| app.global.clone()),
+                entry: app.entry.clone(),
+                args,
+            });
+            let glue = glue.map(|glue| (format!("{}.glue", key), wrap_glue(&app, &glue)));
+            Ok((base, glue))
+        })
+        .buffered(fetcher.concurrency())
+        .try_collect()
+        .await?;
+    let (bin, glue): (Vec<Base>, Vec<Option<(String, String)>>) = modules.into_iter().unzip();
+    Ok((bin, glue.into_iter().flatten().collect()))
+}
+
+// the glue declares its global with let, so inside a function it stays local
+// the runtime picks it up from htmlpacker.glue[name] instead
+fn wrap_glue(app: &WasmApp, glue: &str) -> String {
+    format!(
+        "(function() {{\n{}\nwindow.htmlpacker = window.htmlpacker || {{}};\n\
+        (window.htmlpacker.glue = window.htmlpacker.glue || {{}})[{}] = {};\n}})();",
+        glue,
+        serde_json::Value::from(app.name.as_str()),
+        app.global,
+    )
+}
+
+
 
 
 
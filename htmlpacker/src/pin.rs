@@ -0,0 +1,237 @@
+/*
+* pin.rs
+*
+* `htmlpacker config.yaml --pin`
+* fetch every remote entry that has no integrity yet
+* and write its sha256 back into the yaml
+* the rewrite is done on the text so comments and layout survive
+*/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use url::Url;
+
+use crate::cli::{YamlAssets, YamlRoot};
+use crate::error::PackError;
+use crate::fetcher::{self, Fetcher};
+use crate::packer::load_config;
+
+// returns how many entries got pinned
+pub async fn pin_config(config_path: &Path, offline: bool) -> Result<usize, PackError> {
+    // also validates the whole config before we touch it
+    let mut config = load_config(config_path.to_path_buf()).await?;
+    config.fetch.offline |= offline;
+
+    let text = fs::read_to_string(config_path)
+        .map_err(|source| PackError::ConfigRead {
+            path: config_path.to_path_buf(),
+            source,
+        })?;
+    let root: YamlRoot = serde_yaml::from_str(&text)
+        .map_err(|e| PackError::ConfigParse {
+            path: Some(config_path.to_path_buf()),
+            key: None,
+            message: e.to_string(),
+        })?;
+
+    let pack = root.pack;
+    let sections = [
+        ("favicon", pack.favicon.map(|favicon| favicon.sources)),
+        ("css", pack.css),
+        ("html", pack.html),
+        ("scripts", pack.scripts),
+    ];
+    let fetcher = Fetcher::new(&config.fetch)?;
+    let mut pins: BTreeMap<String, String> = BTreeMap::new();
+    for (section, assets) in sections {
+        for (key, url) in unpinned(section, assets) {
+            if pins.contains_key(&url) {
+                continue;
+            }
+            let parsed = Url::parse(&url).map_err(|e| PackError::ConfigParse {
+                path: Some(config_path.to_path_buf()),
+                key: Some(key.clone()),
+                message: format!("invalid URL '{}': {}", url, e),
+            })?;
+            let bytes = fetcher.get_remote_bytes(&parsed)
+                .await
+                .map_err(|e| e.with_key(&key))?;
+            let integrity = fetcher::integrity(&bytes, "sha256")
+                .unwrap_or_default();
+            println!("📌 {} {}", integrity, url);
+            pins.insert(url, integrity);
+        }
+    }
+
+    if pins.is_empty() {
+        println!("Every remote entry is already pinned.");
+        return Ok(0);
+    }
+    let (pinned_text, count) = pin_yaml(&text, &pins);
+    fs::write(config_path, pinned_text)
+        .map_err(|source| PackError::Output {
+            target: config_path.display().to_string(),
+            source,
+        })?;
+    println!("Pinned {} entries in {}", count, config_path.display());
+    Ok(count)
+}
+
+// (config key, url) of every remote entry without integrity
+fn unpinned(section: &str, assets: Option<YamlAssets>) -> Vec<(String, String)> {
+    assets.and_then(|assets| assets.remote)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.integrity().is_none())
+        .map(|(i, entry)| (format!("{}.remote[{}]", section, i), entry.url().to_string()))
+        .collect()
+}
+
+// turn `- <url>` into `- url: <url>` + `integrity: ...`
+// and give `- url: <url>` entries their missing integrity line
+// only the remote lists of the pinned sections are touched,
+// and every line keeps the line ending it came with
+fn pin_yaml(text: &str, pins: &BTreeMap<String, String>) -> (String, usize) {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut out = String::with_capacity(text.len());
+    // (indent, key) of the mappings the current line sits in
+    let mut keys: Vec<(usize, &str)> = Vec::new();
+    let mut count = 0;
+
+    for (i, raw_line) in lines.iter().enumerate() {
+        let line = raw_line.trim_end_matches(['\r', '\n']);
+        let ending = &raw_line[line.len()..];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let content = line.trim_start();
+        let mut extra: Vec<String> = Vec::new();
+
+        if !content.is_empty() && !content.starts_with('#') {
+            let item = content.strip_prefix("- ");
+            // a list may sit at the same indent as the key it belongs to
+            keys.retain(|(depth, _)| match item {
+                Some(_) => *depth <= indent.len(),
+                None => *depth < indent.len(),
+            });
+            if let Some(item) = item.filter(|_| in_pinned_remote(&keys)) {
+                if let Some(raw) = item.trim_start().strip_prefix("url:") {
+                    let integrity = pins.get(&yaml_scalar(raw.trim()));
+                    // the entry runs on as long as lines are indented deeper than the dash
+                    let has_integrity = lines[i + 1..].iter()
+                        .take_while(|next| {
+                            next.trim().is_empty()
+                                || next.len() - next.trim_start().len() > indent.len()
+                        })
+                        .any(|next| next.trim_start().starts_with("integrity:"));
+                    if let (Some(integrity), false) = (integrity, has_integrity) {
+                        extra.push(format!("{}  integrity: {}", indent, integrity));
+                        count += 1;
+                    }
+                } else {
+                    let raw = item.trim();
+                    if let Some(integrity) = pins.get(&yaml_scalar(raw)) {
+                        out.push_str(&format!("{}- url: {}", indent, raw));
+                        out.push_str(if ending.is_empty() { eol } else { ending });
+                        out.push_str(&format!("{}  integrity: {}", indent, integrity));
+                        out.push_str(ending);
+                        count += 1;
+                        continue;
+                    }
+                }
+            } else if let Some((key, _)) = content.split_once(':').filter(|_| item.is_none()) {
+                keys.push((indent.len(), key.trim()));
+            }
+        }
+
+        out.push_str(line);
+        for extra in extra {
+            out.push_str(if ending.is_empty() { eol } else { ending });
+            out.push_str(&extra);
+        }
+        out.push_str(ending);
+    }
+    (out, count)
+}
+
+// pack.<section>.remote, or pack.favicon.sources.remote
+fn in_pinned_remote(keys: &[(usize, &str)]) -> bool {
+    let path: Vec<&str> = keys.iter().map(|(_, key)| *key).collect();
+    matches!(
+        path.as_slice(),
+        ["pack", "css" | "html" | "scripts", "remote"]
+            | ["pack", "favicon", "sources", "remote"]
+    )
+}
+
+// the plain value of a yaml scalar: no trailing comment, no quotes
+fn yaml_scalar(raw: &str) -> String {
+    let raw = raw.split(" #").next().unwrap_or(raw).trim();
+    raw.trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA: &str = "sha256-abc";
+
+    fn pins(urls: &[&str]) -> BTreeMap<String, String> {
+        urls.iter().map(|url| (url.to_string(), SHA.to_string())).collect()
+    }
+
+    #[test]
+    fn bare_entries_become_pinned_maps() {
+        let text = "pack:\n  css:\n    remote:\n      - https://a.com/a.css # theme\n";
+        let (pinned, count) = pin_yaml(text, &pins(&["https://a.com/a.css"]));
+        assert_eq!(count, 1);
+        assert_eq!(
+            pinned,
+            "pack:\n  css:\n    remote:\n      - url: https://a.com/a.css # theme\n        integrity: sha256-abc\n",
+        );
+    }
+
+    #[test]
+    fn url_entries_get_an_integrity_line() {
+        let text = "pack:\n  scripts:\n    remote:\n    - url: \"https://a.com/a.js\"\n      cache: false\n";
+        let (pinned, count) = pin_yaml(text, &pins(&["https://a.com/a.js"]));
+        assert_eq!(count, 1);
+        assert_eq!(
+            pinned,
+            "pack:\n  scripts:\n    remote:\n    - url: \"https://a.com/a.js\"\n      integrity: sha256-abc\n      cache: false\n",
+        );
+    }
+
+    #[test]
+    fn pinned_and_commented_entries_stay() {
+        let text = "pack:\n  html:\n    remote:\n      - url: https://a.com/a.html\n        integrity: sha256-old\n      # - https://a.com/a.html\n";
+        assert_eq!(pin_yaml(text, &pins(&["https://a.com/a.html"])), (text.to_string(), 0));
+    }
+
+    #[test]
+    fn only_remote_lists_of_the_pinned_sections_change() {
+        let text = "pack:\n  css:\n    local:\n      - https://a.com/a.css\n  wasm:\n    remote:\n      - https://a.com/a.css\n  favicon:\n    sources:\n      remote:\n        - https://a.com/a.css\n";
+        let (pinned, count) = pin_yaml(text, &pins(&["https://a.com/a.css"]));
+        assert_eq!(count, 1);
+        assert_eq!(
+            pinned,
+            text.replace(
+                "        - https://a.com/a.css\n",
+                "        - url: https://a.com/a.css\n          integrity: sha256-abc\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn line_endings_are_kept() {
+        let text = "pack:\r\n  css:\r\n    remote:\r\n      - https://a.com/a.css\r\n      - url: https://b.com/b.css";
+        let (pinned, count) = pin_yaml(text, &pins(&["https://a.com/a.css", "https://b.com/b.css"]));
+        assert_eq!(count, 2);
+        assert_eq!(
+            pinned,
+            "pack:\r\n  css:\r\n    remote:\r\n      - url: https://a.com/a.css\r\n        integrity: sha256-abc\r\n      - url: https://b.com/b.css\r\n        integrity: sha256-abc",
+        );
+    }
+}
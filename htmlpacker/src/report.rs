@@ -0,0 +1,363 @@
+/*
+* report.rs
+*
+* where the bytes of a packed document went
+* one line per asset: raw, compressed and encoded size,
+* and its share of the final file
+* the budgets of the config are checked against the same numbers
+*/
+
+use serde::Serialize;
+
+use crate::config::BudgetConfig;
+use crate::encoder::Base;
+use crate::error::PackError;
+use crate::inspect::human_size;
+
+// everything no asset accounts for: tags, attributes, meta, whitespace
+const MARKUP: &str = "markup";
+// the kinds of text a self extracting page moves into its payload
+const PAYLOAD_KINDS: [&str; 4] = ["js", "css", "html", "font"];
+
+#[derive(Debug, Serialize)]
+pub struct AssetSize {
+    // the config key the asset came from, like wasm.app or css.local[0]
+    pub name: String,
+    pub kind: String,
+    // the codec the blob was packed with, none for text
+    pub compression: String,
+    // bytes as read
+    pub raw: usize,
+    // text after minification, when it was minified
+    pub minified: Option<usize>,
+    // after compression, the same as raw when nothing was compressed
+    pub compressed: usize,
+    // what the asset adds to the document
+    pub encoded: usize,
+    // encoded / total
+    pub share: f64,
+    pub budget: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SizeReport {
+    pub total: usize,
+    pub total_budget: Option<u64>,
+    // largest first, once finished
+    pub assets: Vec<AssetSize>,
+    #[serde(skip)]
+    pub budgets: Option<BudgetConfig>,
+}
+
+impl SizeReport {
+    pub fn new(budgets: Option<BudgetConfig>) -> Self {
+        SizeReport {
+            total_budget: budgets.as_ref().and_then(|budgets| budgets.total),
+            budgets,
+            ..Default::default()
+        }
+    }
+
+    // scripts, styles and fragments go into the page as they are
+    pub fn add_text(&mut self, name: &str, kind: &str, text: &str) {
+        self.add_minified(name, kind, text.len(), text);
+    }
+
+    // the same after minification, raw is the size before
+    pub fn add_minified(&mut self, name: &str, kind: &str, raw: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.push(name, kind, "none", raw, text.len(), text.len());
+        if raw != text.len() {
+            if let Some(asset) = self.assets.last_mut() {
+                asset.minified = Some(text.len());
+            }
+        }
+    }
+
+    pub fn add_blob(&mut self, name: &str, kind: &str, base: &Base) {
+        let compression = base.compression.as_str();
+        self.push(name, kind, compression, base.raw_size, base.compressed_size, encoded_len(&base.text));
+    }
+
+    // self_extracting, the text moved into the payload is only there compressed
+    // each row gets its part of the payload by its length,
+    // the payload row keeps the rest, the markup that went along
+    pub fn add_payload(&mut self, name: &str, base: &Base) {
+        let encoded = encoded_len(&base.text);
+        let part = |len: usize, of: usize| len * of / base.raw_size.max(1);
+        let (mut text, mut compressed, mut encoded_parts) = (0, 0, 0);
+        for asset in &mut self.assets {
+            if PAYLOAD_KINDS.contains(&asset.kind.as_str()) {
+                let len = asset.encoded;
+                asset.compression = base.compression.as_str().to_string();
+                asset.compressed = part(len, base.compressed_size);
+                asset.encoded = part(len, encoded);
+                text += len;
+                compressed += asset.compressed;
+                encoded_parts += asset.encoded;
+            }
+        }
+        self.push(
+            name,
+            "payload",
+            base.compression.as_str(),
+            base.raw_size.saturating_sub(text),
+            base.compressed_size.saturating_sub(compressed),
+            encoded.saturating_sub(encoded_parts),
+        );
+    }
+
+    // sizes known some other way, like the data uris of a favicon set
+    pub fn add(&mut self, name: &str, kind: &str, raw: usize, compressed: usize, encoded: usize) {
+        self.push(name, kind, "none", raw, compressed, encoded);
+    }
+
+    fn push(
+        &mut self,
+        name: &str,
+        kind: &str,
+        compression: &str,
+        raw: usize,
+        compressed: usize,
+        encoded: usize,
+    ) {
+        let budget = self.budget_for(name).map(|(limit, _)| limit);
+        self.assets.push(AssetSize {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            compression: compression.to_string(),
+            raw,
+            minified: None,
+            compressed,
+            encoded,
+            share: 0.0,
+            budget,
+        });
+    }
+
+    // the document is done, the rest of it is markup
+    pub fn finish(&mut self, total: usize) {
+        let assets: usize = self.assets.iter().map(|asset| asset.encoded).sum();
+        let markup = total.saturating_sub(assets);
+        self.add(MARKUP, "html", markup, markup, markup);
+        self.total = total;
+        for asset in &mut self.assets {
+            // four digits are plenty for a percentage with one decimal
+            let share = asset.encoded as f64 / total.max(1) as f64;
+            asset.share = (share * 10000.0).round() / 10000.0;
+        }
+        self.assets.sort_by_key(|asset| std::cmp::Reverse(asset.encoded));
+    }
+
+    // an asset's own limit first, then the one for every asset
+    // markup is not an asset, it only has a limit when named
+    fn budget_for(&self, name: &str) -> Option<(u64, String)> {
+        let budgets = self.budgets.as_ref()?;
+        if let Some(limit) = budgets.assets.get(name) {
+            return Some((*limit, format!("budgets.assets.{}", name)));
+        }
+        match name == MARKUP {
+            true => None,
+            false => budgets.asset.map(|limit| (limit, "budgets.asset".to_string())),
+        }
+    }
+
+    pub fn print_table(&self) {
+        println!(
+            "  {:<28} {:<24} {:<8} {:>10} {:>10} {:>11} {:>10} {:>7}",
+            "asset", "kind", "codec", "raw", "minified", "compressed", "encoded", "share",
+        );
+        for asset in &self.assets {
+            let over = match asset.budget {
+                Some(limit) if asset.encoded as u64 > limit => {
+                    format!("  ❌ over {}", human_size(limit as usize))
+                }
+                _ => String::new(),
+            };
+            let minified = asset.minified.map_or("-".to_string(), human_size);
+            println!(
+                "  {:<28} {:<24} {:<8} {:>10} {:>10} {:>11} {:>10} {:>6.1}%{}",
+                asset.name, asset.kind, asset.compression,
+                human_size(asset.raw), minified, human_size(asset.compressed),
+                human_size(asset.encoded), asset.share * 100.0, over,
+            );
+        }
+        let over = match self.total_budget {
+            Some(limit) if self.total as u64 > limit => {
+                format!("  ❌ over {}", human_size(limit as usize))
+            }
+            _ => String::new(),
+        };
+        println!("📦 {:<95} {:>10}{}", "total", human_size(self.total), over);
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("size report is plain data")
+    }
+
+    // every asset over its limit is reported, the first one is the error
+    pub fn check_budgets(&self) -> Result<(), PackError> {
+        let Some(budgets) = &self.budgets else {
+            return Ok(());
+        };
+        for name in budgets.assets.keys() {
+            if !self.assets.iter().any(|asset| asset.name == *name) {
+                eprintln!("⚠️  budgets.assets.{} matches no asset in the report", name);
+            }
+        }
+
+        let mut over: Vec<PackError> = vec![];
+        if let Some(limit) = budgets.total {
+            if self.total as u64 > limit {
+                over.push(PackError::OverBudget {
+                    target: "the document".to_string(),
+                    size: self.total,
+                    limit,
+                    key: Some("budgets.total".to_string()),
+                });
+            }
+        }
+        for asset in &self.assets {
+            let Some((limit, key)) = self.budget_for(&asset.name) else {
+                continue;
+            };
+            if asset.encoded as u64 > limit {
+                over.push(PackError::OverBudget {
+                    target: asset.name.clone(),
+                    size: asset.encoded,
+                    limit,
+                    key: Some(key),
+                });
+            }
+        }
+
+        let mut over = over.into_iter();
+        match over.next() {
+            Some(first) => {
+                for error in over {
+                    eprintln!("❌ {}", error);
+                }
+                Err(first)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// base122 text can hold < and >, the page escapes them as &lt; and &gt;
+fn encoded_len(text: &str) -> usize {
+    let escapes = text.bytes().filter(|byte| matches!(byte, b'<' | b'>')).count();
+    text.len() + escapes * 3
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::CompressionType;
+
+    fn blob(text: &str, raw_size: usize, compressed_size: usize) -> Base {
+        let mut base = Base::new("bin".to_string(), String::new(), text.to_string());
+        base.compression = CompressionType::Deflate;
+        base.raw_size = raw_size;
+        base.compressed_size = compressed_size;
+        base
+    }
+
+    fn sizes(report: &SizeReport, name: &str) -> (usize, usize, usize) {
+        let asset = report.assets.iter().find(|asset| asset.name == name).unwrap();
+        (asset.raw, asset.compressed, asset.encoded)
+    }
+
+    fn budgets(total: Option<u64>, asset: Option<u64>, assets: &[(&str, u64)]) -> SizeReport {
+        SizeReport::new(Some(BudgetConfig {
+            total,
+            asset,
+            assets: assets.iter().map(|(name, limit)| (name.to_string(), *limit)).collect::<BTreeMap<_, _>>(),
+        }))
+    }
+
+    #[test]
+    fn blobs_count_their_escaped_text() {
+        let mut report = SizeReport::new(None);
+        report.add_blob("wasm.app", "wasm", &blob("ab<>", 10, 8));
+        assert_eq!(sizes(&report, "wasm.app"), (10, 8, 10));
+    }
+
+    #[test]
+    fn payload_is_shared_by_the_text_it_holds() {
+        let mut report = SizeReport::new(None);
+        report.add_text("scripts.local[0]", "js", &"j".repeat(600));
+        report.add_text("css.local[0]", "css", &"c".repeat(200));
+        report.add_blob("assets.img", "image/png", &blob("png", 3, 3));
+        report.add_payload("self_extracting", &blob(&"p".repeat(400), 1000, 250));
+
+        assert_eq!(sizes(&report, "scripts.local[0]"), (600, 150, 240));
+        assert_eq!(sizes(&report, "css.local[0]"), (200, 50, 80));
+        // the markup that went along keeps the rest
+        assert_eq!(sizes(&report, "self_extracting"), (200, 50, 80));
+        assert_eq!(sizes(&report, "assets.img"), (3, 3, 3));
+        let encoded: usize = report.assets.iter()
+            .filter(|asset| asset.name != "assets.img")
+            .map(|asset| asset.encoded)
+            .sum();
+        assert_eq!(encoded, 400);
+    }
+
+    #[test]
+    fn finish_adds_markup_and_sorts_by_size() {
+        let mut report = SizeReport::new(None);
+        report.add_text("css.local[0]", "css", &"c".repeat(200));
+        report.add_text("scripts.local[0]", "js", &"j".repeat(500));
+        report.finish(1000);
+
+        let names: Vec<&str> = report.assets.iter().map(|asset| asset.name.as_str()).collect();
+        assert_eq!(names, ["scripts.local[0]", "markup", "css.local[0]"]);
+        assert_eq!(sizes(&report, "markup"), (300, 300, 300));
+        let shares: Vec<f64> = report.assets.iter().map(|asset| asset.share).collect();
+        assert_eq!(shares, [0.5, 0.3, 0.2]);
+        assert_eq!(report.total, 1000);
+    }
+
+    #[test]
+    fn an_asset_limit_beats_the_common_one() {
+        let report = budgets(None, Some(10), &[("css.local[0]", 100), ("markup", 5)]);
+        assert_eq!(report.budget_for("css.local[0]"), Some((100, "budgets.assets.css.local[0]".to_string())));
+        assert_eq!(report.budget_for("scripts.local[0]"), Some((10, "budgets.asset".to_string())));
+        // markup only has a limit when it is named
+        assert_eq!(report.budget_for("markup"), Some((5, "budgets.assets.markup".to_string())));
+        assert_eq!(budgets(None, Some(10), &[]).budget_for("markup"), None);
+    }
+
+    #[test]
+    fn over_budget_is_an_error() {
+        let mut report = budgets(None, Some(100), &[("css.local[0]", 1000)]);
+        report.add_text("css.local[0]", "css", &"c".repeat(500));
+        report.add_text("scripts.local[0]", "js", &"j".repeat(101));
+        report.finish(2000);
+        match report.check_budgets() {
+            Err(PackError::OverBudget { target, size, limit, key }) => {
+                assert_eq!(target, "scripts.local[0]");
+                assert_eq!((size, limit), (101, 100));
+                assert_eq!(key.as_deref(), Some("budgets.asset"));
+            }
+            other => panic!("expected an asset over budget, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_total_has_a_budget_too() {
+        let mut report = budgets(Some(1000), None, &[]);
+        report.add_text("scripts.local[0]", "js", &"j".repeat(500));
+        report.finish(999);
+        assert!(report.check_budgets().is_ok());
+        report.finish(1001);
+        assert!(matches!(
+            report.check_budgets(),
+            Err(PackError::OverBudget { size: 1001, limit: 1000, .. }),
+        ));
+    }
+}
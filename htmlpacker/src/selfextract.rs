@@ -0,0 +1,241 @@
+/*
+* selfextract.rs
+*
+* `self_extracting: true`
+* the styles, scripts and body of a packed document go into one
+* compressed payload, a small bootstrap puts them back in place on open
+* blobs are compressed already and stay outside, where the runtime finds them
+* the head keeps its title, meta tags and icons for link previews
+* extract puts a payload back into its page, for unpack
+*/
+
+use lol_html::html_content::ContentType;
+use std::cell::RefCell;
+
+use lol_html::{element, rewrite_str, text, RewriteStrSettings};
+use maud::{html, PreEscaped};
+use sha2::{Digest, Sha256};
+
+use crate::config::{CompressionLevel, CompressionType, EncodingType, MinifyConfig};
+use crate::encoder;
+use crate::error::PackError;
+use crate::html as page_html;
+use crate::minify;
+use crate::report::SizeReport;
+
+const BOOTSTRAP_JS: &str = include_str!("../core/bootstrap.js");
+const PAYLOAD_ID: &str = "htmlpacker-payload";
+const BOOTSTRAP_ATTRIBUTE: &str = "data-htmlpacker-bootstrap";
+
+// put around what moves, cut out again right after
+const MOVE_START: &str = "<!--htmlpacker:move-->";
+const MOVE_END: &str = "<!--/htmlpacker:move-->";
+const KEEP_START: &str = "<!--htmlpacker:keep-->";
+const KEEP_END: &str = "<!--/htmlpacker:keep-->";
+const BODY_START: &str = "<!--htmlpacker:body-->";
+const BODY_END: &str = "<!--/htmlpacker:body-->";
+
+// the page with its payload, the report gets the payload and the bootstrap
+pub fn self_extracting(
+    document: &str,
+    minify: &MinifyConfig,
+    report: &mut SizeReport,
+) -> Result<String, PackError> {
+    let marked = mark(document).map_err(|e| PackError::Encoding {
+        id: PAYLOAD_ID.to_string(),
+        key: Some("self_extracting".to_string()),
+        message: format!("cannot rewrite html: {}", e),
+    })?;
+    let (Some(body_start), Some(body_end)) = (marked.find(BODY_START), marked.rfind(BODY_END)) else {
+        return Err(PackError::Unsupported {
+            key: Some("self_extracting".to_string()),
+            message: "the document has no <body> to extract into".to_string(),
+        });
+    };
+
+    // styles and scripts of the head
+    let (head_kept, head_moved) = split(&marked[..body_start], MOVE_START, MOVE_END);
+    // everything in the body but the blobs
+    let body = &marked[body_start + BODY_START.len()..body_end];
+    let (body_moved, blobs) = split(body, KEEP_START, KEEP_END);
+
+    let payload = format!(
+        "<!DOCTYPE html><html><head>{}</head><body>{}</body></html>",
+        head_moved, body_moved,
+    );
+    // the bootstrap only has the browser's DecompressionStream
+    let base = encoder::encode_bytes_with_level(
+        payload.as_bytes(),
+        PAYLOAD_ID,
+        CompressionType::Auto,
+        CompressionLevel::default(),
+        EncodingType::Base64,
+        &CompressionType::NATIVE_CODECS,
+    )?;
+
+    let bootstrap_js = minify::text(BOOTSTRAP_JS.to_string(), "js", "bootstrap.js", minify);
+    let bootstrap = html! {
+        "\n"
+        pre id=(PAYLOAD_ID) hash=(base.hash) encoding=(base.encoding.as_str())
+            compression=(base.compression.as_str()) mime="text/html"
+            style="display: none;" {
+            "\n"
+            (base.text)
+            "\n"
+        }
+        "\n"
+        script data-htmlpacker-bootstrap { "\n"(PreEscaped(&bootstrap_js))"\n" }
+        "\n"
+    }.into_string();
+
+    let mut page = String::with_capacity(head_kept.len() + blobs.len() + bootstrap.len());
+    page.push_str(&head_kept);
+    page.push_str(&blobs);
+    page.push_str(&bootstrap);
+    page.push_str(&marked[body_end + BODY_END.len()..]);
+
+    report.add_payload("self_extracting", &base);
+    report.add_minified("self_extracting.bootstrap", "js", BOOTSTRAP_JS.len(), &bootstrap_js);
+    Ok(page)
+}
+
+// the page as it was before self_extracting, None when it has no payload
+// the head part goes back to the end of the head,
+// the body part to where the bootstrap was
+pub fn extract(page: &str) -> Result<Option<String>, String> {
+    let Some(payload) = read_payload(page).map_err(|e| format!("cannot read html: {}", e))? else {
+        return Ok(None);
+    };
+    let compression = CompressionType::from_name(&payload.compression)
+        .ok_or(format!("unknown compression {}", payload.compression))?;
+    let bytes = encoder::decode_bytes(
+        page_html::blob_text(&payload.text),
+        compression,
+        EncodingType::Base64,
+    )?;
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if payload.hash.as_ref().is_some_and(|expected| *expected != actual) {
+        return Err(format!("{}: sha256 mismatch", PAYLOAD_ID));
+    }
+    let text = String::from_utf8(bytes).map_err(|e| format!("{}: {}", PAYLOAD_ID, e))?;
+
+    let parts = text.strip_prefix("<!DOCTYPE html><html><head>")
+        .and_then(|rest| rest.strip_suffix("</body></html>"))
+        .and_then(|rest| rest.split_once("</head><body>"));
+    let Some((head, body)) = parts else {
+        return Err(format!("{}: not a payload written by self_extracting", PAYLOAD_ID));
+    };
+    let selector = format!("script[{}]", BOOTSTRAP_ATTRIBUTE);
+    let extracted = rewrite_str(page, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("head", |el| {
+                el.append(head, ContentType::Html);
+                Ok(())
+            }),
+            element!(format!("pre#{}", PAYLOAD_ID), |el| {
+                el.remove();
+                Ok(())
+            }),
+            element!(selector, |el| {
+                el.replace(body, ContentType::Html);
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    });
+    extracted.map(Some).map_err(|e| format!("cannot rewrite html: {}", e))
+}
+
+#[derive(Debug, Default)]
+struct Payload {
+    hash: Option<String>,
+    compression: String,
+    text: String,
+}
+
+fn read_payload(page: &str) -> Result<Option<Payload>, lol_html::errors::RewritingError> {
+    let payload: RefCell<Option<Payload>> = RefCell::new(None);
+    let selector = format!("pre#{}", PAYLOAD_ID);
+    rewrite_str(page, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(selector, |el| {
+                *payload.borrow_mut() = Some(Payload {
+                    hash: el.get_attribute("hash"),
+                    compression: el.get_attribute("compression").unwrap_or("none".to_string()),
+                    ..Default::default()
+                });
+                Ok(())
+            }),
+            text!(selector, |chunk| {
+                if let Some(payload) = payload.borrow_mut().as_mut() {
+                    payload.text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    })?;
+    Ok(payload.into_inner())
+}
+
+// wrap what moves into the payload and what stays behind in comments
+fn mark(document: &str) -> Result<String, lol_html::errors::RewritingError> {
+    rewrite_str(document, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("head style, head script", |el| {
+                el.before(MOVE_START, ContentType::Html);
+                el.after(MOVE_END, ContentType::Html);
+                Ok(())
+            }),
+            element!("body", |el| {
+                el.prepend(BODY_START, ContentType::Html);
+                el.append(BODY_END, ContentType::Html);
+                Ok(())
+            }),
+            element!("body > pre[id][hash]", |el| {
+                el.before(KEEP_START, ContentType::Html);
+                el.after(KEEP_END, ContentType::Html);
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    })
+}
+
+// (outside the markers, inside them), both in document order
+fn split(text: &str, start: &str, end: &str) -> (String, String) {
+    let mut outside = String::with_capacity(text.len());
+    let mut inside = String::new();
+    let mut rest = text;
+    while let Some(at) = rest.find(start) {
+        outside.push_str(&rest[..at]);
+        rest = &rest[at + start.len()..];
+        let stop = rest.find(end).unwrap_or(rest.len());
+        inside.push_str(&rest[..stop]);
+        rest = rest.get(stop + end.len()..).unwrap_or("");
+    }
+    outside.push_str(rest);
+    (outside, inside)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_undoes_self_extracting() {
+        let document = "<!DOCTYPE html><html><head><title>t</title><style>p{}</style></head>\
+            <body><p>hi</p><pre id=\"bin\" hash=\"h\">x</pre><script>run()</script></body></html>";
+        let mut report = SizeReport::new(None);
+        let page = self_extracting(document, &MinifyConfig::default(), &mut report).unwrap();
+        assert!(!page.contains("run()"));
+
+        let extracted = extract(&page).unwrap().expect("the page has a payload");
+        for part in ["<title>t</title>", "<style>p{}</style>", "<p>hi</p>", "<script>run()</script>", "id=\"bin\""] {
+            assert!(extracted.contains(part), "{} is missing from {}", part, extracted);
+        }
+        assert!(!extracted.contains(PAYLOAD_ID));
+        assert!(!extracted.contains(BOOTSTRAP_ATTRIBUTE));
+        assert_eq!(extract(document).unwrap(), None);
+    }
+}
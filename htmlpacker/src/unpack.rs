@@ -0,0 +1,424 @@
+/*
+* unpack.rs
+*
+* `htmlpacker unpack index.html -d out/`
+* take a packed page apart again, for debugging and auditing
+* every <pre> blob is decoded and checked against its sha256,
+* inline scripts, styles and the html fragments of the body become files
+* page.html is what is left, with a comment where each piece was
+* a self extracting page has its payload put back in place first
+*/
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, text, RewriteStrSettings};
+use sha2::{Digest, Sha256};
+
+use crate::config::{CompressionType, EncodingType};
+use crate::encoder;
+use crate::error::PackError;
+use crate::html;
+use crate::inspect::human_size;
+use crate::selfextract;
+
+// wrapped around every top level element of the body in the second pass
+const FRAGMENT_START: &str = "<!--htmlpacker:fragment-->";
+const FRAGMENT_END: &str = "<!--/htmlpacker:fragment-->";
+
+// one <pre> blob, attributes as written by html::binary
+#[derive(Debug, Default)]
+struct Blob {
+    id: String,
+    hash: Option<String>,
+    encoding: String,
+    compression: String,
+    mime: Option<String>,
+    wasm: bool,
+    text: String,
+}
+
+#[derive(Debug, Default)]
+struct Page {
+    blobs: Vec<Blob>,
+    // (extension, source)
+    scripts: Vec<(&'static str, String)>,
+    styles: Vec<String>,
+}
+
+pub fn unpack(path: &Path, dir: &Path) -> Result<(), PackError> {
+    let html = fs::read_to_string(path).map_err(|source| PackError::AssetRead {
+        path: path.to_path_buf(),
+        key: None,
+        source,
+    })?;
+    let html_error = |e: lol_html::errors::RewritingError| PackError::Unpack {
+        target: path.display().to_string(),
+        message: format!("cannot read html: {}", e),
+    };
+    // a self extracting page goes back to the page it was first
+    let html = match selfextract::extract(&html) {
+        Ok(Some(extracted)) => {
+            println!("✅ self extracting payload  sha256 ok");
+            extracted
+        }
+        Ok(None) => html,
+        Err(message) => return Err(PackError::Unpack {
+            target: path.display().to_string(),
+            message,
+        }),
+    };
+    let page = read_page(&html).map_err(html_error)?;
+    let mut problems: Vec<String> = vec![];
+
+    // blobs -------------------------------------------------------------- /
+    let mut blob_files: Vec<String> = vec![];
+    for blob in &page.blobs {
+        // the id becomes a file name, it must not lead out of dir
+        if !is_plain_id(&blob.id) {
+            problems.push(format!("{:?}: not a blob id that can be a file name", blob.id));
+            blob_files.push(String::new());
+            continue;
+        }
+        let bytes = match decode_blob(blob) {
+            Ok(bytes) => bytes,
+            Err(message) => {
+                problems.push(format!("{}: {}", blob.id, message));
+                blob_files.push(String::new());
+                continue;
+            }
+        };
+        let name = format!("blobs/{}.{}", blob.id, blob_extension(blob, &bytes));
+        write_file(dir, &name, &bytes)?;
+        let actual = format!("{:x}", Sha256::digest(&bytes));
+        match &blob.hash {
+            Some(expected) if *expected == actual => {
+                println!("✅ {}  {}  sha256 ok", name, human_size(bytes.len()));
+            }
+            Some(expected) => problems.push(format!(
+                "{}: sha256 mismatch\n  expected {}\n  actual   {}",
+                name, expected, actual,
+            )),
+            None => println!("⚠️ {}  {}  no hash to check", name, human_size(bytes.len())),
+        }
+        blob_files.push(name);
+    }
+
+    // scripts and styles ------------------------------------------------- /
+    let script_files: Vec<String> = page.scripts.iter().enumerate()
+        .map(|(i, (extension, _))| format!("scripts/{:02}.{}", i + 1, extension))
+        .collect();
+    for (name, (_, source)) in script_files.iter().zip(&page.scripts) {
+        write_file(dir, name, source.as_bytes())?;
+    }
+    let style_files: Vec<String> = (1..=page.styles.len())
+        .map(|i| format!("styles/{:02}.css", i))
+        .collect();
+    for (name, source) in style_files.iter().zip(&page.styles) {
+        write_file(dir, name, source.as_bytes())?;
+    }
+    println!("✅ {} scripts, {} styles", script_files.len(), style_files.len());
+
+    // fragments and what is left of the page ----------------------------- /
+    let skeleton = skeleton(&html, &blob_files, &script_files, &style_files)
+        .map_err(html_error)?;
+    let mut fragments = 0;
+    let mut rest = skeleton.as_str();
+    let mut page_html = String::with_capacity(skeleton.len());
+    while let Some(start) = rest.find(FRAGMENT_START) {
+        page_html.push_str(&rest[..start]);
+        rest = &rest[start + FRAGMENT_START.len()..];
+        let end = rest.find(FRAGMENT_END).unwrap_or(rest.len());
+        fragments += 1;
+        let name = format!("fragments/{:02}.html", fragments);
+        write_file(dir, &name, &rest.as_bytes()[..end])?;
+        page_html.push_str(&format!("<!-- htmlpacker unpack: {} -->", name));
+        rest = rest.get(end + FRAGMENT_END.len()..).unwrap_or("");
+    }
+    page_html.push_str(rest);
+    write_file(dir, "page.html", page_html.as_bytes())?;
+    println!("✅ {} fragments, page.html", fragments);
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("❌ {}", problem);
+        }
+        return Err(PackError::Unpack {
+            target: path.display().to_string(),
+            message: format!(
+                "{} of {} blobs failed to verify", problems.len(), page.blobs.len()
+            ),
+        });
+    }
+    println!("📦 unpacked {} into {}", path.display(), dir.display());
+    Ok(())
+}
+
+// first pass, collect the blobs, scripts and styles in document order
+fn read_page(html: &str) -> Result<Page, lol_html::errors::RewritingError> {
+    let page = RefCell::new(Page::default());
+    // scripts with a src have nothing inline to write out
+    let in_script = RefCell::new(false);
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(html::BLOB_SELECTOR, |el| {
+                page.borrow_mut().blobs.push(Blob {
+                    id: el.get_attribute("id").unwrap_or_default(),
+                    hash: el.get_attribute("hash"),
+                    encoding: el.get_attribute("encoding").unwrap_or("base64".to_string()),
+                    compression: el.get_attribute("compression").unwrap_or("none".to_string()),
+                    mime: el.get_attribute("mime"),
+                    wasm: el.has_attribute("data-wasm"),
+                    text: String::new(),
+                });
+                Ok(())
+            }),
+            text!(html::BLOB_SELECTOR, |chunk| {
+                if let Some(blob) = page.borrow_mut().blobs.last_mut() {
+                    blob.text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+            element!("script", |el| {
+                let inline = !el.has_attribute("src");
+                *in_script.borrow_mut() = inline;
+                if inline {
+                    let extension = script_extension(el.get_attribute("type"));
+                    page.borrow_mut().scripts.push((extension, String::new()));
+                }
+                Ok(())
+            }),
+            text!("script", |chunk| {
+                if *in_script.borrow() {
+                    if let Some((_, source)) = page.borrow_mut().scripts.last_mut() {
+                        source.push_str(chunk.as_str());
+                    }
+                }
+                Ok(())
+            }),
+            element!("style", |_| {
+                page.borrow_mut().styles.push(String::new());
+                Ok(())
+            }),
+            text!("style", |chunk| {
+                if let Some(source) = page.borrow_mut().styles.last_mut() {
+                    source.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    })?;
+    Ok(page.into_inner())
+}
+
+// second pass, every piece written out becomes a comment naming its file
+// the same elements in the same order as read_page saw them
+fn skeleton(
+    html: &str,
+    blob_files: &[String],
+    script_files: &[String],
+    style_files: &[String],
+) -> Result<String, lol_html::errors::RewritingError> {
+    let placeholder = |name: Option<&String>| match name {
+        Some(name) if !name.is_empty() => format!("<!-- htmlpacker unpack: {} -->", name),
+        _ => "<!-- htmlpacker unpack: not decoded -->".to_string(),
+    };
+    let blobs = RefCell::new(blob_files.iter());
+    let scripts = RefCell::new(script_files.iter());
+    let styles = RefCell::new(style_files.iter());
+    let skeleton = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("body > *", |el| {
+                let blob = el.tag_name() == "pre"
+                    && el.has_attribute("id") && el.has_attribute("hash") && el.has_attribute("encoding");
+                if !blob && !matches!(el.tag_name().as_str(), "script" | "style") {
+                    el.before(FRAGMENT_START, ContentType::Html);
+                    el.after(FRAGMENT_END, ContentType::Html);
+                }
+                Ok(())
+            }),
+            element!(html::BLOB_SELECTOR, |el| {
+                el.replace(&placeholder(blobs.borrow_mut().next()), ContentType::Html);
+                Ok(())
+            }),
+            element!("script", |el| {
+                if !el.has_attribute("src") {
+                    el.replace(&placeholder(scripts.borrow_mut().next()), ContentType::Html);
+                }
+                Ok(())
+            }),
+            element!("style", |el| {
+                el.replace(&placeholder(styles.borrow_mut().next()), ContentType::Html);
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    })?;
+    Ok(skeleton)
+}
+
+fn decode_blob(blob: &Blob) -> Result<Vec<u8>, String> {
+    let compression = CompressionType::from_name(&blob.compression)
+        .ok_or(format!("unknown compression {}", blob.compression))?;
+    let encoding = EncodingType::from_name(&blob.encoding)
+        .ok_or(format!("unknown encoding {}", blob.encoding))?;
+    // maud escaped the text when it wrote the page
+    let text = unescape(html::blob_text(&blob.text));
+    encoder::decode_bytes(&text, compression, encoding)
+}
+
+// the few entities maud writes, base122 text can contain all of them
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn blob_extension(blob: &Blob, bytes: &[u8]) -> &'static str {
+    if blob.wasm || blob.id.starts_with("bin-wasm-") {
+        return "wasm";
+    }
+    let mime = match &blob.mime {
+        Some(mime) => mime.as_str(),
+        None => encoder::sniff_mime(bytes, None),
+    };
+    match mime {
+        "image/svg+xml" => "svg",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/x-icon" => "ico",
+        "application/wasm" => "wasm",
+        "font/ttf" => "ttf",
+        "font/otf" => "otf",
+        "font/woff" => "woff",
+        "font/woff2" => "woff2",
+        "text/css" => "css",
+        "text/javascript" => "js",
+        "text/html" => "html",
+        "application/json" => "json",
+        "text/plain" => "txt",
+        "application/xml" => "xml",
+        "application/pdf" => "pdf",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/wav" => "wav",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        _ => "bin",
+    }
+}
+
+fn script_extension(script_type: Option<String>) -> &'static str {
+    match script_type {
+        Some(script_type) if script_type.contains("json") || script_type == "importmap" => "json",
+        _ => "js",
+    }
+}
+
+// letters, digits, - and _, like every id the packer writes
+fn is_plain_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn write_file(dir: &Path, name: &str, contents: &[u8]) -> Result<(), PackError> {
+    let path = dir.join(name);
+    let output_error = |source| PackError::Output {
+        target: path.display().to_string(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(output_error)?;
+    }
+    fs::write(&path, contents).map_err(output_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::CompressionType;
+    use crate::encoder::Base;
+
+    // pack a page into its own temp dir and unpack it to dir/out
+    fn unpack_page(test: &str, html_texts: Vec<String>, bin: Vec<Base>) -> (Result<(), PackError>, PathBuf) {
+        let page = html::page(
+            String::new(), vec![], html_texts, vec![], bin,
+            html::PageMeta { meta: None, image: None },
+        ).into_string();
+        let dir = std::env::temp_dir()
+            .join(format!("htmlpacker-unpack-{}-{}", test, std::process::id()));
+        let path = dir.join("index.html");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, page).unwrap();
+        (unpack(&path, &dir.join("out")), dir)
+    }
+
+    fn blob(id: &str, payload: &[u8]) -> Base {
+        encoder::encode_bytes(payload, id, CompressionType::None, EncodingType::Base64).unwrap()
+    }
+
+    // base122 text that starts with a space and ends in whitespace
+    fn edge_payload() -> (Vec<u8>, Base) {
+        (0..=u8::MAX)
+            .map(|last| vec![0x40, 0x55, last])
+            .map(|payload| {
+                let base = encoder::encode_bytes(
+                    &payload, "bin-edge", CompressionType::None, EncodingType::Base122,
+                ).unwrap();
+                (payload, base)
+            })
+            .find(|(_, base)| base.text.ends_with([' ', '\t']))
+            .expect("some last byte encodes to trailing whitespace")
+    }
+
+    #[test]
+    fn round_trip_keeps_whitespace_at_the_edges() {
+        let (payload, base) = edge_payload();
+        assert!(base.text.starts_with(' '));
+        let (result, dir) = unpack_page("edges", vec![], vec![base]);
+        let bytes = fs::read(dir.join("out/blobs/bin-edge.bin"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(bytes.unwrap(), payload);
+    }
+
+    #[test]
+    fn blob_ids_cannot_leave_the_output_dir() {
+        let (result, dir) = unpack_page("traversal", vec![], vec![
+            blob("../../escaped", b"outside"),
+            blob("..", b"parent"),
+            blob("a/b", b"nested"),
+        ]);
+        let escaped = dir.join("escaped.bin").exists() || dir.join("out/a").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(PackError::Unpack { .. })), "{:?}", result);
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn a_pre_of_the_page_is_not_a_blob() {
+        let fragment = "<pre id=\"code\">fn main() {}</pre>".to_string();
+        let (result, dir) = unpack_page("pre", vec![fragment], vec![blob("bin-a", b"a")]);
+        let blobs = fs::read_dir(dir.join("out/blobs")).map(|entries| entries.count());
+        let fragment = fs::read_to_string(dir.join("out/fragments/01.html"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(blobs.unwrap(), 1);
+        assert!(fragment.unwrap().contains("fn main() {}"));
+    }
+}
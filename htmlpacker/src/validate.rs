@@ -0,0 +1,84 @@
+/*
+* validate.rs
+*
+* `htmlpacker validate config.yaml`
+* everything a build would trip over, found without packing:
+* the yaml itself, local files, wasm modules and their dependencies
+* nothing is compiled, fetched or written
+*/
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::AssetSource;
+use crate::error::PackError;
+use crate::fragment;
+use crate::packer::{load_config, native_codec_message};
+use crate::wasmbuilder;
+
+pub async fn validate(config_path: &Path) -> Result<(), PackError> {
+    // parse errors and invalid values stop right here
+    let mut config = load_config(config_path.to_path_buf()).await?;
+    let mut problems: Vec<String> = vec![];
+
+    let local_files = config.local_files();
+    for (key, path) in &local_files {
+        if !path.is_file() {
+            problems.push(format!("{} does not exist (config key `{}`)", path.display(), key));
+        }
+    }
+    let local_count = local_files.len();
+
+    // fragments that would break the page are only worth a warning
+    for (key, path) in local_files.iter().filter(|(key, _)| key.starts_with("html.")) {
+        if let Ok(html) = fs::read_to_string(path) {
+            for problem in fragment::unbalanced(&html) {
+                eprintln!("⚠️  {}: {}", key, problem);
+            }
+        }
+    }
+
+    if config.favicon_set.is_some() && config.favicon.as_ref().is_none_or(Vec::is_empty) {
+        problems.push("favicon.generate needs a favicon to start from".to_string());
+    }
+
+    for (key, compression) in config.native_codec_conflicts() {
+        problems.push(format!("{} (config key `{}`)", native_codec_message(compression), key));
+    }
+
+    for module in config.wasm.iter().flatten().filter(|module| module.compile_wasm) {
+        let problem = match &module.source {
+            AssetSource::Local(path) => wasmbuilder::extract_module_dir(path).err(),
+            AssetSource::Remote(url) => Some(format!(
+                "cannot compile remote module {}, set compile_wasm: false", url
+            )),
+            AssetSource::Inline(_) => None,
+        };
+        problems.extend(problem.map(|problem| format!("wasm.{}: {}", module.id, problem)));
+    }
+    if let Some(modules) = config.wasm.take() {
+        match wasmbuilder::order_wasm_modules(modules) {
+            Err(PackError::ConfigParse { message, key: Some(key), .. }) => {
+                problems.push(format!("{} (config key `{}`)", message, key));
+            }
+            Err(e) => problems.push(e.to_string()),
+            Ok(_) => (),
+        }
+    }
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("❌ {}", problem);
+        }
+        return Err(PackError::ConfigParse {
+            path: Some(config_path.to_path_buf()),
+            key: None,
+            message: match problems.len() {
+                1 => "1 problem found".to_string(),
+                n => format!("{} problems found", n),
+            },
+        });
+    }
+    println!("✅ {} is valid, {} local files", config_path.display(), local_count);
+    Ok(())
+}
@@ -5,25 +5,37 @@
 * works with as many modules as are passed in
 */
 
-use std::error::Error;
+use std::collections::BTreeSet;
 use std::process::Command;
 use std::path::Path;
 use tokio::task;
 
 use crate::config::{WasmModule, AssetSource};
+use crate::error::PackError;
 
 // pass in all the modules you need to be compiled with wasm_pack
 pub async fn compile_wasm_modules(
     modules: &[WasmModule],
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), PackError> {
 
     // make a list of all the modules to compile
     let mut compile_futures = Vec::new();
     for module in modules {
         if module.compile_wasm {
+            // a downloaded module comes prebuilt
+            if let AssetSource::Remote(url) = &module.source {
+                return Err(PackError::WasmBuild {
+                    module_id: module.id.clone(),
+                    message: format!("cannot compile remote module {}, set compile_wasm: false", url),
+                });
+            }
             if let AssetSource::Local(path) = &module.source {
                 // get project directory from path
-                let module_dir = extract_module_dir(path)?;
+                let module_dir = extract_module_dir(path)
+                    .map_err(|message| PackError::WasmBuild {
+                        module_id: module.id.clone(),
+                        message,
+                    })?;
                 println!("Queue compilation for {}: {}", module.id, module_dir);
                 let future = Box::pin(build_wasm(module_dir));
                 compile_futures.push((
@@ -45,19 +57,25 @@ pub async fn compile_wasm_modules(
 
     let results = futures::future::join_all(futures).await;
 
-    let mut failed = false;
-    for (id, result) in ids.iter().zip(results.iter()) {
+    // report every module, then fail on the first broken one
+    let mut failed = None;
+    for (id, result) in ids.into_iter().zip(results) {
         match result {
             Ok(_) => println!("✅ {} compiled successfully", id),
-            Err(err) => {
-                eprintln!("❌ {} build failed: {}", id, err);
-                failed = true;
+            Err(message) => {
+                eprintln!("❌ {} build failed: {}", id, message);
+                if failed.is_none() {
+                    failed = Some(PackError::WasmBuild {
+                        module_id: id,
+                        message,
+                    });
+                }
             }
         }
     }
 
-    if failed {
-        return Err("One or more WASM builds failed".into());
+    if let Some(err) = failed {
+        return Err(err);
     }
 
     println!("All WASM builds compiled successfully!");
@@ -65,11 +83,69 @@ pub async fn compile_wasm_modules(
     Ok(())
 }
 
+// apps start in the order they appear in the page
+// sorted by order then name, each one moved after its dependencies
+// blobs that are not apps keep their place in front
+pub fn order_wasm_modules(modules: Vec<WasmModule>) -> Result<Vec<WasmModule>, PackError> {
+    let dependency_error = |name: &str, message: String| PackError::ConfigParse {
+        path: None,
+        key: Some(format!("wasm.{}.depends_on", name)),
+        message,
+    };
+    let (mut ordered, mut apps): (Vec<WasmModule>, Vec<WasmModule>) = modules
+        .into_iter()
+        .partition(|module| module.app.is_none());
+    apps.sort_by(|a, b| {
+        let (a, b) = (a.app.as_ref().unwrap(), b.app.as_ref().unwrap());
+        (a.order, &a.name).cmp(&(b.order, &b.name))
+    });
+
+    let mut names = BTreeSet::new();
+    for app in apps.iter().filter_map(|module| module.app.as_ref()) {
+        if !names.insert(app.name.as_str()) {
+            return Err(PackError::ConfigParse {
+                path: None,
+                key: Some(format!("wasm.{}", app.name)),
+                message: "two wasm apps share this name".to_string(),
+            });
+        }
+    }
+    for app in apps.iter().filter_map(|module| module.app.as_ref()) {
+        if let Some(unknown) = app.depends_on.iter().find(|dep| !names.contains(dep.as_str())) {
+            return Err(dependency_error(
+                &app.name,
+                format!("depends on '{}', which is not a wasm module", unknown),
+            ));
+        }
+    }
+
+    let mut started: BTreeSet<String> = BTreeSet::new();
+    while !apps.is_empty() {
+        let ready = apps.iter().position(|module| {
+            let app = module.app.as_ref().unwrap();
+            app.depends_on.iter().all(|dep| started.contains(dep))
+        });
+        let Some(ready) = ready else {
+            let waiting: Vec<&str> = apps.iter()
+                .map(|module| module.app.as_ref().unwrap().name.as_str())
+                .collect();
+            return Err(dependency_error(
+                waiting[0],
+                format!("circular dependency between {}", waiting.join(", ")),
+            ));
+        };
+        let module = apps.remove(ready);
+        started.insert(module.app.as_ref().unwrap().name.clone());
+        ordered.push(module);
+    }
+    Ok(ordered)
+}
+
 // from the config relative path we want the grandparent path
 // ex: ../wasm_decoder/pkg/wasm_decoder_bg.wasm -> ../wasm_decoder
-fn extract_module_dir(
+pub fn extract_module_dir(
     wasm_path: &Path,
-) -> Result<String, Box<dyn Error>> {
+) -> Result<String, String> {
     if let Some(parent) = wasm_path.parent() {
         if parent.ends_with("pkg") {
             if let Some(module_dir) = parent.parent() {
@@ -82,7 +158,7 @@ fn extract_module_dir(
         "cannot extract project directory from path: {:?}. \
         Expected pattern like '../module/pkg/wasm_module_bg.wasm'",
         wasm_path
-    ).into())
+    ))
 }
 
 
@@ -92,7 +168,7 @@ fn extract_module_dir(
 // wasm-pack build --target no-modules
 async fn build_wasm(
     dir: String,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), String> {
     println!("Building WASM in {}", dir);
 
     let dir_copy = dir.clone();
@@ -110,11 +186,12 @@ async fn build_wasm(
             ])
             .status()
     })
-    .await?;
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("cannot run wasm-pack in {}: {}", &dir_copy, e))?;
 
-    if !status?.success() {
-        //println!("HELO THERE!");
-        return Err(format!("Failed to compiled WASM in {}", &dir_copy).into());
+    if !status.success() {
+        return Err(format!("Failed to compiled WASM in {}", &dir_copy));
     }
 
     println!("WASM compiled in {}.", &dir_copy);
@@ -146,3 +223,67 @@ async fn build_wasm(
     println!("All WASM builds completed successfully!");
     Ok(())
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WasmApp;
+
+    fn app(name: &str, order: i64, depends_on: &[&str]) -> WasmModule {
+        WasmModule {
+            id: format!("bin-{}", name),
+            app: Some(WasmApp {
+                name: name.to_string(),
+                order,
+                depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn ids(modules: Vec<WasmModule>) -> Vec<String> {
+        modules.into_iter().map(|module| module.id).collect()
+    }
+
+    #[test]
+    fn apps_start_after_their_dependencies() {
+        let blob = WasmModule { id: "bin-data".to_string(), ..Default::default() };
+        let modules = vec![
+            app("ui", 0, &["engine", "audio"]),
+            app("engine", 5, &["physics"]),
+            blob,
+            app("physics", 9, &[]),
+            app("audio", 1, &[]),
+            app("extra", 1, &[]),
+        ];
+        assert_eq!(
+            ids(order_wasm_modules(modules).unwrap()),
+            ["bin-data", "bin-audio", "bin-extra", "bin-physics", "bin-engine", "bin-ui"],
+        );
+    }
+
+    #[test]
+    fn circular_dependencies_are_an_error() {
+        let modules = vec![app("a", 0, &["b"]), app("b", 0, &["c"]), app("c", 0, &["a"]), app("d", 0, &[])];
+        match order_wasm_modules(modules) {
+            Err(PackError::ConfigParse { key, message, .. }) => {
+                assert_eq!(key.as_deref(), Some("wasm.a.depends_on"));
+                assert_eq!(message, "circular dependency between a, b, c");
+            }
+            other => panic!("expected a circular dependency, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_dependencies_are_an_error() {
+        let modules = vec![app("a", 0, &[]), app("b", 0, &["a", "missing"])];
+        match order_wasm_modules(modules) {
+            Err(PackError::ConfigParse { key, message, .. }) => {
+                assert_eq!(key.as_deref(), Some("wasm.b.depends_on"));
+                assert_eq!(message, "depends on 'missing', which is not a wasm module");
+            }
+            other => panic!("expected an unknown dependency, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+/*
+* watch.rs
+*
+* `htmlpacker watch config.yaml`
+* pack, then pack again every time a local source changes
+* the latest page is served on localhost,
+* with a small script that reloads it after each successful rebuild
+* wasm crates are only recompiled when their own files change
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+// crates
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+// local
+use crate::config::{AssetSource, PackerConfig};
+use crate::error::PackError;
+use crate::html;
+use crate::packer::{load_config, Packer};
+use crate::wasmbuilder;
+
+// events arriving this close together are one rebuild
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// next to the config's own files, these may be pulled in by url() or a fragment
+const WEB_EXTENSIONS: [&str; 19] = [
+    "css", "html", "htm", "js", "mjs", "json", "wasm", "svg", "png", "jpg", "jpeg",
+    "gif", "webp", "avif", "ico", "woff", "woff2", "ttf", "otf",
+];
+const LIVE_RELOAD_PATH: &str = "/__htmlpacker/livereload";
+// only added to the served page, never to the file on disk
+const LIVE_RELOAD_SCRIPT: &str = "<script>\n\
+    // htmlpacker watch, reload after every successful rebuild\n\
+    new EventSource('/__htmlpacker/livereload').onmessage = () => location.reload();\n\
+    </script>\n";
+
+// what to watch for one config, rebuilt whenever the config changes
+#[derive(Debug, Default)]
+struct WatchSet {
+    dirs: BTreeMap<PathBuf, RecursiveMode>,
+    // every local file the config names, the config included
+    files: BTreeSet<PathBuf>,
+    // crates built with compile_wasm, a change here means wasm-pack
+    crates: Vec<PathBuf>,
+    // our own output, changes here never trigger a rebuild
+    ignored: Vec<PathBuf>,
+}
+
+pub async fn watch(
+    config_path: PathBuf,
+    output: PathBuf,
+    port: u16,
+    offline: bool,
+) -> Result<(), PackError> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await
+        .map_err(|source| PackError::Output {
+            target: format!("127.0.0.1:{}", port),
+            source,
+        })?;
+    let (page_tx, page_rx) = watch::channel(Arc::new(String::new()));
+    tokio::spawn(serve(listener, page_rx));
+
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let config = load_config(config_path.clone()).await?;
+    let mut set = watch_set(&config, &config_path, &output);
+    // only kept alive, dropping it stops the watch
+    let mut _watcher = watch_dirs(&set, &event_tx)?;
+    println!("👀 watching {} directories, serving http://127.0.0.1:{}", set.dirs.len(), port);
+
+    // the first build compiles what the config asks for
+    let mut compile = true;
+    loop {
+        rebuild(&config_path, &output, compile, offline, &page_tx).await;
+
+        // wait for a relevant change, then let the burst settle
+        let mut changed: Vec<PathBuf> = vec![];
+        while changed.is_empty() {
+            let Some(event) = event_rx.recv().await else {
+                return Ok(());
+            };
+            changed.extend(relevant_paths(event, &set));
+            tokio::time::sleep(DEBOUNCE).await;
+            while let Ok(event) = event_rx.try_recv() {
+                changed.extend(relevant_paths(event, &set));
+            }
+        }
+        changed.sort();
+        changed.dedup();
+        for path in &changed {
+            println!("🔄 {}", path.display());
+        }
+        compile = changed.iter().any(|path| set.crates.iter().any(|dir| path.starts_with(dir)));
+
+        // the config may point at new files now
+        // a broken config keeps the old watch set until it is fixed
+        let config_file = absolute(&config_path);
+        if changed.contains(&config_file) {
+            if let Ok(config) = load_config(config_path.clone()).await {
+                set = watch_set(&config, &config_path, &output);
+                _watcher = watch_dirs(&set, &event_tx)?;
+                println!("👀 watching {} directories", set.dirs.len());
+            }
+        }
+    }
+}
+
+// a failed rebuild is reported and the last good page stays up
+async fn rebuild(
+    config_path: &Path,
+    output: &Path,
+    compile: bool,
+    offline: bool,
+    page_tx: &watch::Sender<Arc<String>>,
+) {
+    let started = Instant::now();
+    let result = async {
+        let mut config = load_config(config_path.to_path_buf()).await?;
+        config.fetch.offline |= offline;
+        if !compile {
+            for module in config.wasm.iter_mut().flatten() {
+                module.compile_wasm = false;
+            }
+        }
+        let page = Packer::new(config).pack_to_string().await?;
+        html::save_html(page.clone(), output.to_path_buf())?;
+        Ok::<String, PackError>(page)
+    }.await;
+    match result {
+        Ok(page) => {
+            println!("✅ rebuilt {} in {} ms", output.display(), started.elapsed().as_millis());
+            page_tx.send_replace(Arc::new(page));
+        }
+        Err(e) => eprintln!("❌ {}", e),
+    }
+}
+
+// every local file of the config, by the directory it lives in
+// entry documents and wasm crates are watched with everything below them
+fn watch_set(config: &PackerConfig, config_path: &Path, output: &Path) -> WatchSet {
+    let mut set = WatchSet::default();
+    let mut add = |dir: PathBuf, mode: RecursiveMode| {
+        let dir = absolute(&dir);
+        if dir.is_dir() {
+            let entry = set.dirs.entry(dir).or_insert(mode);
+            if mode == RecursiveMode::Recursive {
+                *entry = mode;
+            }
+        }
+    };
+    let parent = |path: &Path| match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    add(parent(config_path), RecursiveMode::NonRecursive);
+    if let Some(AssetSource::Local(entry)) = &config.entry {
+        add(parent(entry), RecursiveMode::Recursive);
+    }
+    for (_, path) in config.local_files() {
+        add(parent(path), RecursiveMode::NonRecursive);
+    }
+    set.files.insert(absolute(config_path));
+    set.files.extend(config.local_files().into_iter().map(|(_, path)| absolute(path)));
+    for module in config.wasm.iter().flatten().filter(|module| module.compile_wasm) {
+        let AssetSource::Local(path) = &module.source else {
+            continue;
+        };
+        if let Ok(dir) = wasmbuilder::extract_module_dir(path) {
+            let dir = PathBuf::from(dir);
+            add(dir.clone(), RecursiveMode::Recursive);
+            let dir = absolute(&dir);
+            // wasm-pack writes these, watching them would loop forever
+            set.ignored.push(dir.join("target"));
+            set.ignored.push(dir.join("pkg"));
+            set.crates.push(dir);
+        }
+    }
+
+    set.ignored.push(absolute(output));
+    set.ignored.extend(config.fetch.lockfile.as_deref().map(absolute));
+    set.ignored.extend(config.fetch.cache_dir.as_deref().map(absolute));
+    set
+}
+
+// a fresh watcher for the directories of a new watch set
+// the old one stops watching when it is dropped
+fn watch_dirs(
+    set: &WatchSet,
+    event_tx: &mpsc::UnboundedSender<notify::Result<notify::Event>>,
+) -> Result<RecommendedWatcher, PackError> {
+    let event_tx = event_tx.clone();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = event_tx.send(event);
+    }).map_err(watch_error)?;
+    for (dir, mode) in &set.dirs {
+        watcher.watch(dir, *mode).map_err(watch_error)?;
+    }
+    Ok(watcher)
+}
+
+// changed files we care about, editor swap files and our own output left out
+fn relevant_paths(event: notify::Result<notify::Event>, set: &WatchSet) -> Vec<PathBuf> {
+    let Ok(event) = event else {
+        return vec![];
+    };
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+        return vec![];
+    }
+    event.paths.into_iter()
+        .filter(|path| !set.ignored.iter().any(|ignored| path.starts_with(ignored)))
+        .filter(|path| {
+            let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            !(name.starts_with('.') || name.ends_with('~') || name.ends_with(".swp"))
+        })
+        .filter(|path| set.files.contains(path) || in_tree(path, set) || is_web_file(path))
+        .collect()
+}
+
+// below an entry document's directory or a wasm crate, anything goes
+fn in_tree(path: &Path, set: &WatchSet) -> bool {
+    set.dirs.iter()
+        .filter(|(_, mode)| **mode == RecursiveMode::Recursive)
+        .any(|(dir, _)| path.starts_with(dir))
+}
+
+fn is_web_file(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|extension| WEB_EXTENSIONS.contains(&extension.as_str()))
+}
+
+// notify reports absolute paths, the config holds relative ones
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    // files that do not exist yet, like the first output
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => absolute(parent).join(name),
+        _ => std::env::current_dir().unwrap_or_default().join(path),
+    }
+}
+
+fn watch_error(error: notify::Error) -> PackError {
+    PackError::Unsupported {
+        key: None,
+        message: format!("cannot watch files: {}", error),
+    }
+}
+
+// server ------------------------------------------------------------------- /
+// just enough http for one page and its live reload stream
+async fn serve(listener: TcpListener, page_rx: watch::Receiver<Arc<String>>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        tokio::spawn(respond(stream, page_rx.clone()));
+    }
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    mut page_rx: watch::Receiver<Arc<String>>,
+) -> std::io::Result<()> {
+    // the request line is all we look at
+    let mut request = vec![0; 4096];
+    let read = stream.read(&mut request).await?;
+    let request = String::from_utf8_lossy(&request[..read]);
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    if method != "GET" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"").await;
+    }
+    if path == LIVE_RELOAD_PATH {
+        // server sent events, one message per successful rebuild
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\
+            Connection: keep-alive\r\n\r\n"
+        ).await?;
+        page_rx.mark_unchanged();
+        while page_rx.changed().await.is_ok() {
+            stream.write_all(b"data: reload\n\n").await?;
+        }
+        return Ok(());
+    }
+    match path.split('?').next() {
+        Some("/") | Some("/index.html") => {
+            let page = with_live_reload(&page_rx.borrow());
+            write_response(&mut stream, "200 OK", "text/html; charset=utf-8", page.as_bytes()).await
+        }
+        _ => write_response(&mut stream, "404 Not Found", "text/plain", b"not found").await,
+    }
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+        Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+// right before </body>, or at the very end
+fn with_live_reload(page: &str) -> String {
+    match page.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &page[..end], LIVE_RELOAD_SCRIPT, &page[end..]),
+        None => format!("{}{}", page, LIVE_RELOAD_SCRIPT),
+    }
+}
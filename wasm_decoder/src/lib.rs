@@ -11,6 +11,8 @@ so we need web-sys here too?
 no, we just do the js in js
 this will serve exclusively as:
     - brolti decompressor
+    - gzip, deflate and zstd decompressors
+    - base122 decoder
     - base94 decoder
 so that we can have multiple building blocks
 this is just the unpacker
@@ -20,8 +22,8 @@ js in js or just wasm it all?
 
 */
 
-use brotli;
 //use base94;
+use std::io::Read;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen(js_name = decompress)]
@@ -37,6 +39,82 @@ pub fn decompress(buf: Box<[u8]>) -> Result<Box<[u8]>, JsValue> {
     Ok(out.into_boxed_slice())
 }
 
+// the other codecs a blob can be packed with
+// deflate is zlib wrapped, like DecompressionStream('deflate')
+#[wasm_bindgen(js_name = decompressGzip)]
+pub fn decompress_gzip(buf: Box<[u8]>) -> Result<Box<[u8]>, JsValue> {
+    let mut out = Vec::<u8>::new();
+    flate2::read::GzDecoder::new(buf.as_ref())
+        .read_to_end(&mut out)
+        .map_err(|e| JsValue::from_str(&format!("Gzip decompress failed: {}", e)))?;
+    Ok(out.into_boxed_slice())
+}
+
+#[wasm_bindgen(js_name = decompressDeflate)]
+pub fn decompress_deflate(buf: Box<[u8]>) -> Result<Box<[u8]>, JsValue> {
+    let mut out = Vec::<u8>::new();
+    flate2::read::ZlibDecoder::new(buf.as_ref())
+        .read_to_end(&mut out)
+        .map_err(|e| JsValue::from_str(&format!("Deflate decompress failed: {}", e)))?;
+    Ok(out.into_boxed_slice())
+}
+
+#[wasm_bindgen(js_name = decompressZstd)]
+pub fn decompress_zstd(buf: Box<[u8]>) -> Result<Box<[u8]>, JsValue> {
+    let mut out = Vec::<u8>::new();
+    ruzstd::decoding::StreamingDecoder::new(buf.as_ref())
+        .map_err(|e| JsValue::from_str(&format!("Zstd decompress failed: {}", e)))?
+        .read_to_end(&mut out)
+        .map_err(|e| JsValue::from_str(&format!("Zstd decompress failed: {}", e)))?;
+    Ok(out.into_boxed_slice())
+}
+
+// base122 text back to bytes
+// https://github.com/kevinAlbs/Base122
+// every char carries 7 bits, two byte chars carry one of the
+// illegal html chars (or nothing when shortened) plus 7 more bits
+const BASE122_ILLEGALS: [u8; 6] = [0, 10, 13, 34, 38, 92];
+const BASE122_SHORTENED: u32 = 0b111;
+
+#[wasm_bindgen(js_name = decodeBase122)]
+pub fn decode_base122(text: &str) -> Result<Box<[u8]>, JsValue> {
+    let mut out = Vec::<u8>::with_capacity(text.len() * 7 / 8);
+    let mut cur_byte: u8 = 0;
+    let mut bit_of_byte: u32 = 0;
+
+    let mut push7 = |bits: u8| {
+        let bits = bits << 1;
+        cur_byte |= bits >> bit_of_byte;
+        bit_of_byte += 7;
+        if bit_of_byte >= 8 {
+            out.push(cur_byte);
+            bit_of_byte -= 8;
+            cur_byte = ((bits as u32) << (7 - bit_of_byte)) as u8;
+        }
+    };
+
+    for c in text.chars() {
+        let c = c as u32;
+        if c > 127 {
+            let illegal_index = (c >> 8) & 0b111;
+            if illegal_index != BASE122_SHORTENED {
+                match BASE122_ILLEGALS.get(illegal_index as usize) {
+                    Some(&illegal) => push7(illegal),
+                    None => return Err(JsValue::from_str(&format!(
+                        "Base122 decode failed: invalid char {:#x}", c
+                    ))),
+                }
+            }
+            push7((c & 127) as u8);
+        } else if c != '\n' as u32 {
+            // newlines are never part of the data, only layout
+            push7(c as u8);
+        }
+    }
+
+    Ok(out.into_boxed_slice())
+}
+
 /*
 pub fn set_panic_hook() {
     #[cfg(feature="console_error_panic_hook")]